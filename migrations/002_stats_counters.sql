@@ -0,0 +1,51 @@
+-- Cached aggregates for the /stats endpoint
+
+-- Running counts maintained by triggers, so /stats never runs a full COUNT(*)
+CREATE TABLE IF NOT EXISTS stats_counters (
+    name VARCHAR(50) PRIMARY KEY,
+    value BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO stats_counters (name, value)
+SELECT 'data_submissions', COUNT(*) FROM data_submissions
+UNION ALL
+SELECT 'devices', COUNT(DISTINCT device_id) FROM device_events WHERE event_type = 'registered'
+UNION ALL
+SELECT 'verifiers', COUNT(*) FROM (
+    SELECT DISTINCT ON (verifier_address) event_type
+    FROM verifier_events
+    ORDER BY verifier_address, block_number DESC, id DESC
+) latest
+WHERE event_type = 'added'
+ON CONFLICT (name) DO NOTHING;
+
+CREATE OR REPLACE FUNCTION bump_stats_counters() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_TABLE_NAME = 'data_submissions' THEN
+        UPDATE stats_counters SET value = value + 1 WHERE name = 'data_submissions';
+    ELSIF TG_TABLE_NAME = 'device_events' AND NEW.event_type = 'registered' THEN
+        UPDATE stats_counters SET value = value + 1 WHERE name = 'devices';
+    ELSIF TG_TABLE_NAME = 'verifier_events' THEN
+        UPDATE stats_counters
+        SET value = GREATEST(value + CASE NEW.event_type WHEN 'added' THEN 1 ELSE -1 END, 0)
+        WHERE name = 'verifiers';
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_data_submissions_stats
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION bump_stats_counters();
+
+CREATE TRIGGER trg_device_events_stats
+    AFTER INSERT ON device_events
+    FOR EACH ROW EXECUTE FUNCTION bump_stats_counters();
+
+CREATE TRIGGER trg_verifier_events_stats
+    AFTER INSERT ON verifier_events
+    FOR EACH ROW EXECUTE FUNCTION bump_stats_counters();
+
+-- Covers the 24h/7d submission rate and active device queries
+CREATE INDEX IF NOT EXISTS idx_data_submissions_timestamp_device
+    ON data_submissions(timestamp, device_id_hash);
@@ -0,0 +1,17 @@
+-- Committed events in commit order, backing /stream/events and its resumption
+
+CREATE TABLE IF NOT EXISTS event_stream (
+    id BIGSERIAL PRIMARY KEY,
+    contract VARCHAR(50) NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    owner VARCHAR(42),
+    device VARCHAR(64),
+    block_number BIGINT NOT NULL,
+    tx_hash VARCHAR(66) NOT NULL,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_event_stream_event_type ON event_stream(event_type, id);
+CREATE INDEX idx_event_stream_owner ON event_stream(owner, id);
+CREATE INDEX idx_event_stream_device ON event_stream(device, id);
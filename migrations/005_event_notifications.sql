@@ -0,0 +1,38 @@
+-- LISTEN/NOTIFY fan-out of newly indexed rows
+--
+-- Channels, one per event type:
+--   lcore_data_submitted      data_submissions
+--   lcore_device_registered   device_events with event_type 'registered'
+--   lcore_device_updated      device_events with event_type 'updated'
+--
+-- Payload: {"table": ..., "id": ..., "block_number": ..., "tx_hash": ...}
+-- Postgres delivers notifications only once the inserting transaction
+-- commits, so listeners never see rows that were rolled back.
+
+CREATE OR REPLACE FUNCTION notify_indexed_event() RETURNS TRIGGER AS $$
+DECLARE
+    channel TEXT;
+BEGIN
+    IF TG_TABLE_NAME = 'data_submissions' THEN
+        channel := 'lcore_data_submitted';
+    ELSE
+        channel := 'lcore_device_' || NEW.event_type;
+    END IF;
+    
+    PERFORM pg_notify(channel, json_build_object(
+        'table', TG_TABLE_NAME,
+        'id', NEW.id,
+        'block_number', NEW.block_number,
+        'tx_hash', NEW.tx_hash
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_data_submissions_notify
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION notify_indexed_event();
+
+CREATE TRIGGER trg_device_events_notify
+    AFTER INSERT ON device_events
+    FOR EACH ROW EXECUTE FUNCTION notify_indexed_event();
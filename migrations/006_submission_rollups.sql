@@ -0,0 +1,40 @@
+-- Time-series rollups for /analytics/submissions
+
+-- Registry device ID for each deviceIdHash the IoT pipeline reports. The hash
+-- is keccak256, which Postgres cannot compute, so the indexer fills this in
+-- when it indexes a registration (and backfills existing devices at startup)
+CREATE TABLE IF NOT EXISTS device_id_hashes (
+    device_id_hash VARCHAR(64) PRIMARY KEY,
+    device_id VARCHAR(64) NOT NULL UNIQUE
+);
+
+-- Submissions per device and owner per hour, maintained by trigger. Day and
+-- week buckets are sums of hours; zone and device type are resolved through
+-- device_id_hashes at query time
+CREATE TABLE IF NOT EXISTS submission_rollups (
+    hour_start BIGINT NOT NULL,
+    device_id_hash VARCHAR(64) NOT NULL,
+    device_owner VARCHAR(42) NOT NULL,
+    submissions BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (hour_start, device_id_hash, device_owner)
+);
+
+INSERT INTO submission_rollups (hour_start, device_id_hash, device_owner, submissions)
+SELECT timestamp - timestamp % 3600, device_id_hash, device_owner, COUNT(*)
+FROM data_submissions
+GROUP BY 1, 2, 3
+ON CONFLICT DO NOTHING;
+
+CREATE OR REPLACE FUNCTION bump_submission_rollups() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO submission_rollups (hour_start, device_id_hash, device_owner, submissions)
+    VALUES (NEW.timestamp - NEW.timestamp % 3600, NEW.device_id_hash, NEW.device_owner, 1)
+    ON CONFLICT (hour_start, device_id_hash, device_owner)
+    DO UPDATE SET submissions = submission_rollups.submissions + 1;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_data_submissions_rollups
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION bump_submission_rollups();
@@ -0,0 +1,31 @@
+-- Last data submission per device, for stale-device detection
+
+-- Keyed by the deviceIdHash submissions carry; join through device_id_hashes
+-- to reach the registry device
+CREATE TABLE IF NOT EXISTS device_liveness (
+    device_id_hash VARCHAR(64) PRIMARY KEY,
+    last_submission_at BIGINT NOT NULL,
+    last_block_number BIGINT NOT NULL
+);
+
+INSERT INTO device_liveness (device_id_hash, last_submission_at, last_block_number)
+SELECT DISTINCT ON (device_id_hash) device_id_hash, timestamp, block_number
+FROM data_submissions
+ORDER BY device_id_hash, timestamp DESC, block_number DESC
+ON CONFLICT DO NOTHING;
+
+CREATE OR REPLACE FUNCTION track_device_liveness() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO device_liveness (device_id_hash, last_submission_at, last_block_number)
+    VALUES (NEW.device_id_hash, NEW.timestamp, NEW.block_number)
+    ON CONFLICT (device_id_hash) DO UPDATE
+    SET last_submission_at = EXCLUDED.last_submission_at,
+        last_block_number = EXCLUDED.last_block_number
+    WHERE device_liveness.last_submission_at <= EXCLUDED.last_submission_at;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_data_submissions_liveness
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION track_device_liveness();
@@ -0,0 +1,18 @@
+-- Submission-rate anomalies raised by the alert detector
+
+CREATE TABLE IF NOT EXISTS alerts (
+    id BIGSERIAL PRIMARY KEY,
+    scope VARCHAR(20) NOT NULL CHECK (scope IN ('device', 'owner', 'zone')),
+    subject VARCHAR(100) NOT NULL,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('spike', 'drop')),
+    window_start BIGINT NOT NULL,
+    observed BIGINT NOT NULL,
+    baseline_mean DOUBLE PRECISION NOT NULL,
+    baseline_stddev DOUBLE PRECISION NOT NULL,
+    forwarded_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Each anomaly is raised once however often the detector runs
+    UNIQUE (scope, subject, kind, window_start)
+);
+
+CREATE INDEX idx_alerts_unforwarded ON alerts(created_at) WHERE forwarded_at IS NULL;
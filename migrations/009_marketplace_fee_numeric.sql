@@ -0,0 +1,5 @@
+-- Store the marketplace base fee at full uint256 precision
+
+ALTER TABLE marketplace_config ALTER COLUMN base_fee TYPE NUMERIC(78, 0);
+
+CREATE INDEX IF NOT EXISTS idx_marketplace_config_block ON marketplace_config(block_number, id);
@@ -0,0 +1,16 @@
+-- Logs that could not be decoded or held a value out of range for its column
+
+CREATE TABLE IF NOT EXISTS quarantined_events (
+    id BIGSERIAL PRIMARY KEY,
+    contract VARCHAR(50) NOT NULL,
+    topic VARCHAR(66),
+    -- Kept as reported by the node, which may itself be out of range
+    block_number NUMERIC(78, 0),
+    log_index NUMERIC(78, 0),
+    tx_hash VARCHAR(66),
+    error TEXT NOT NULL,
+    raw_log JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_quarantined_events_contract ON quarantined_events(contract, id);
@@ -0,0 +1,16 @@
+-- Quarantined logs become a dead-letter queue for every log indexing fails on
+
+ALTER TABLE quarantined_events RENAME TO failed_events;
+ALTER SEQUENCE quarantined_events_id_seq RENAME TO failed_events_id_seq;
+ALTER INDEX idx_quarantined_events_contract RENAME TO idx_failed_events_contract;
+
+ALTER TABLE failed_events
+    ADD COLUMN attempts INTEGER NOT NULL DEFAULT 1,
+    ADD COLUMN last_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
+
+-- A log replayed after a restart counts as another attempt, not a new entry
+DELETE FROM failed_events a
+USING failed_events b
+WHERE a.contract = b.contract AND a.tx_hash = b.tx_hash AND a.log_index = b.log_index AND a.id < b.id;
+
+CREATE UNIQUE INDEX idx_failed_events_log ON failed_events(contract, tx_hash, log_index);
@@ -0,0 +1,75 @@
+-- One spelling for every address, hash and device ID: lowercase hex with a
+-- 0x prefix. Device IDs and data hashes were stored without the prefix.
+
+CREATE FUNCTION canonical_hex(value TEXT) RETURNS TEXT AS $$
+    SELECT '0x' || lower(regexp_replace(value, '^0[xX]', ''))
+$$ LANGUAGE SQL IMMUTABLE STRICT;
+
+ALTER TABLE device_events ALTER COLUMN device_id TYPE VARCHAR(66);
+ALTER TABLE device_transfers ALTER COLUMN device_id TYPE VARCHAR(66);
+ALTER TABLE data_submissions
+    ALTER COLUMN data_hash TYPE VARCHAR(66),
+    ALTER COLUMN device_id_hash TYPE VARCHAR(66);
+ALTER TABLE event_stream ALTER COLUMN device TYPE VARCHAR(66);
+ALTER TABLE device_id_hashes
+    ALTER COLUMN device_id_hash TYPE VARCHAR(66),
+    ALTER COLUMN device_id TYPE VARCHAR(66);
+ALTER TABLE submission_rollups ALTER COLUMN device_id_hash TYPE VARCHAR(66);
+ALTER TABLE device_liveness ALTER COLUMN device_id_hash TYPE VARCHAR(66);
+
+UPDATE verifier_events SET
+    verifier_address = canonical_hex(verifier_address),
+    tx_hash = canonical_hex(tx_hash);
+
+UPDATE device_events SET
+    device_id = canonical_hex(device_id),
+    owner_address = canonical_hex(owner_address),
+    tx_hash = canonical_hex(tx_hash);
+
+UPDATE device_transfers SET
+    device_id = canonical_hex(device_id),
+    old_owner = canonical_hex(old_owner),
+    new_owner = canonical_hex(new_owner),
+    tx_hash = canonical_hex(tx_hash);
+
+UPDATE data_submissions SET
+    data_hash = canonical_hex(data_hash),
+    device_id_hash = canonical_hex(device_id_hash),
+    device_owner = canonical_hex(device_owner),
+    tx_hash = canonical_hex(tx_hash);
+
+UPDATE marketplace_config SET tx_hash = canonical_hex(tx_hash);
+
+UPDATE ownership_transfers SET
+    previous_owner = canonical_hex(previous_owner),
+    new_owner = canonical_hex(new_owner),
+    tx_hash = canonical_hex(tx_hash);
+
+UPDATE device_id_hashes SET
+    device_id_hash = canonical_hex(device_id_hash),
+    device_id = canonical_hex(device_id);
+
+UPDATE submission_rollups SET
+    device_id_hash = canonical_hex(device_id_hash),
+    device_owner = canonical_hex(device_owner);
+
+UPDATE device_liveness SET device_id_hash = canonical_hex(device_id_hash);
+
+-- Device subjects are device IDs, or device ID hashes for unmapped devices
+UPDATE alerts SET subject = canonical_hex(subject) WHERE scope IN ('device', 'owner');
+
+-- Streamed payloads are the stored rows, so they get the same spelling
+UPDATE event_stream SET
+    owner = canonical_hex(owner),
+    device = canonical_hex(device),
+    tx_hash = canonical_hex(tx_hash),
+    payload = payload || (
+        SELECT COALESCE(jsonb_object_agg(key, canonical_hex(value)), '{}'::jsonb)
+        FROM jsonb_each_text(payload)
+        WHERE key IN (
+            'device_id', 'data_hash', 'device_id_hash', 'owner_address', 'device_owner',
+            'verifier_address', 'tx_hash'
+        )
+    );
+
+DROP FUNCTION canonical_hex(TEXT);
@@ -0,0 +1,70 @@
+-- Store hashes, device IDs and addresses as raw bytes rather than hex text,
+-- halving their size and the size of the indexes over them. Values are
+-- canonical 0x-prefixed hex since 012, so the prefix is dropped and the rest
+-- decoded.
+
+ALTER TABLE verifier_events
+    ALTER COLUMN verifier_address TYPE BYTEA USING decode(substr(verifier_address, 3), 'hex'),
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE device_events
+    ALTER COLUMN device_id TYPE BYTEA USING decode(substr(device_id, 3), 'hex'),
+    ALTER COLUMN owner_address TYPE BYTEA USING decode(substr(owner_address, 3), 'hex'),
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE device_transfers
+    ALTER COLUMN device_id TYPE BYTEA USING decode(substr(device_id, 3), 'hex'),
+    ALTER COLUMN old_owner TYPE BYTEA USING decode(substr(old_owner, 3), 'hex'),
+    ALTER COLUMN new_owner TYPE BYTEA USING decode(substr(new_owner, 3), 'hex'),
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE data_submissions
+    ALTER COLUMN data_hash TYPE BYTEA USING decode(substr(data_hash, 3), 'hex'),
+    ALTER COLUMN device_id_hash TYPE BYTEA USING decode(substr(device_id_hash, 3), 'hex'),
+    ALTER COLUMN device_owner TYPE BYTEA USING decode(substr(device_owner, 3), 'hex'),
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE marketplace_config
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE ownership_transfers
+    ALTER COLUMN previous_owner TYPE BYTEA USING decode(substr(previous_owner, 3), 'hex'),
+    ALTER COLUMN new_owner TYPE BYTEA USING decode(substr(new_owner, 3), 'hex'),
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE event_stream
+    ALTER COLUMN owner TYPE BYTEA USING decode(substr(owner, 3), 'hex'),
+    ALTER COLUMN device TYPE BYTEA USING decode(substr(device, 3), 'hex'),
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substr(tx_hash, 3), 'hex');
+
+ALTER TABLE device_id_hashes
+    ALTER COLUMN device_id_hash TYPE BYTEA USING decode(substr(device_id_hash, 3), 'hex'),
+    ALTER COLUMN device_id TYPE BYTEA USING decode(substr(device_id, 3), 'hex');
+
+ALTER TABLE submission_rollups
+    ALTER COLUMN device_id_hash TYPE BYTEA USING decode(substr(device_id_hash, 3), 'hex'),
+    ALTER COLUMN device_owner TYPE BYTEA USING decode(substr(device_owner, 3), 'hex');
+
+ALTER TABLE device_liveness
+    ALTER COLUMN device_id_hash TYPE BYTEA USING decode(substr(device_id_hash, 3), 'hex');
+
+-- Notifications keep carrying the transaction hash as hex
+CREATE OR REPLACE FUNCTION notify_indexed_event() RETURNS TRIGGER AS $$
+DECLARE
+    channel TEXT;
+BEGIN
+    IF TG_TABLE_NAME = 'data_submissions' THEN
+        channel := 'lcore_data_submitted';
+    ELSE
+        channel := 'lcore_device_' || NEW.event_type;
+    END IF;
+    
+    PERFORM pg_notify(channel, json_build_object(
+        'table', TG_TABLE_NAME,
+        'id', NEW.id,
+        'block_number', NEW.block_number,
+        'tx_hash', '0x' || encode(NEW.tx_hash, 'hex')
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,158 @@
+-- Range-partition data_submissions and device_events by block number
+--
+-- Partitions span 1,000,000 blocks (PARTITION_BLOCKS in src/partitions.rs)
+-- and are named <table>_p<block_number / 1000000>. The indexer creates them
+-- ahead of the blocks it stores; old ones can be detached for retention.
+
+CREATE OR REPLACE FUNCTION ensure_block_partition(parent TEXT, block BIGINT, span BIGINT)
+RETURNS TEXT AS $$
+DECLARE
+    idx BIGINT := block / span;
+    partition TEXT := parent || '_p' || idx;
+BEGIN
+    EXECUTE format(
+        'CREATE TABLE IF NOT EXISTS %I PARTITION OF %I FOR VALUES FROM (%s) TO (%s)',
+        partition, parent, idx * span, (idx + 1) * span
+    );
+    RETURN partition;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Row triggers on a partition see the partition's name in TG_TABLE_NAME, so
+-- the partitioned tables pass their own name as the trigger argument
+CREATE OR REPLACE FUNCTION bump_stats_counters() RETURNS TRIGGER AS $$
+DECLARE
+    source TEXT := COALESCE(TG_ARGV[0], TG_TABLE_NAME);
+BEGIN
+    IF source = 'data_submissions' THEN
+        UPDATE stats_counters SET value = value + 1 WHERE name = 'data_submissions';
+    ELSIF source = 'device_events' AND NEW.event_type = 'registered' THEN
+        UPDATE stats_counters SET value = value + 1 WHERE name = 'devices';
+    ELSIF source = 'verifier_events' THEN
+        UPDATE stats_counters
+        SET value = GREATEST(value + CASE NEW.event_type WHEN 'added' THEN 1 ELSE -1 END, 0)
+        WHERE name = 'verifiers';
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_indexed_event() RETURNS TRIGGER AS $$
+DECLARE
+    source TEXT := COALESCE(TG_ARGV[0], TG_TABLE_NAME);
+    channel TEXT;
+BEGIN
+    IF source = 'data_submissions' THEN
+        channel := 'lcore_data_submitted';
+    ELSE
+        channel := 'lcore_device_' || NEW.event_type;
+    END IF;
+
+    PERFORM pg_notify(channel, json_build_object(
+        'table', source,
+        'id', NEW.id,
+        'block_number', NEW.block_number,
+        'tx_hash', '0x' || encode(NEW.tx_hash, 'hex')
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- data_submissions
+ALTER TABLE data_submissions RENAME TO data_submissions_unpartitioned;
+ALTER SEQUENCE data_submissions_id_seq OWNED BY NONE;
+
+CREATE TABLE data_submissions (
+    id BIGINT NOT NULL DEFAULT nextval('data_submissions_id_seq'),
+    data_hash BYTEA NOT NULL,
+    device_id_hash BYTEA NOT NULL,
+    device_owner BYTEA NOT NULL,
+    timestamp BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    tx_hash BYTEA NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+) PARTITION BY RANGE (block_number);
+
+ALTER SEQUENCE data_submissions_id_seq OWNED BY data_submissions.id;
+
+SELECT ensure_block_partition('data_submissions', block_number, 1000000)
+FROM (SELECT DISTINCT block_number / 1000000 * 1000000 AS block_number FROM data_submissions_unpartitioned) b;
+
+INSERT INTO data_submissions (
+    id, data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash, created_at
+)
+SELECT id, data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash, created_at
+FROM data_submissions_unpartitioned;
+
+DROP TABLE data_submissions_unpartitioned;
+
+-- The partition key has to be part of every unique index
+ALTER TABLE data_submissions ADD PRIMARY KEY (id, block_number);
+CREATE INDEX idx_data_submissions_data_hash ON data_submissions(data_hash);
+CREATE INDEX idx_data_submissions_device_hash ON data_submissions(device_id_hash);
+CREATE INDEX idx_data_submissions_owner ON data_submissions(device_owner);
+CREATE INDEX idx_data_submissions_timestamp ON data_submissions(timestamp);
+CREATE INDEX idx_data_submissions_timestamp_device ON data_submissions(timestamp, device_id_hash);
+
+CREATE TRIGGER trg_data_submissions_stats
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION bump_stats_counters('data_submissions');
+
+CREATE TRIGGER trg_data_submissions_notify
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION notify_indexed_event('data_submissions');
+
+CREATE TRIGGER trg_data_submissions_rollups
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION bump_submission_rollups();
+
+CREATE TRIGGER trg_data_submissions_liveness
+    AFTER INSERT ON data_submissions
+    FOR EACH ROW EXECUTE FUNCTION track_device_liveness();
+
+-- device_events
+ALTER TABLE device_events RENAME TO device_events_unpartitioned;
+ALTER SEQUENCE device_events_id_seq OWNED BY NONE;
+
+CREATE TABLE device_events (
+    id BIGINT NOT NULL DEFAULT nextval('device_events_id_seq'),
+    device_id BYTEA NOT NULL,
+    owner_address BYTEA NOT NULL,
+    event_type VARCHAR(20) NOT NULL CHECK (event_type IN ('registered', 'updated', 'transferred')),
+    device_type INTEGER,
+    zone VARCHAR(100),
+    timestamp BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    tx_hash BYTEA NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+) PARTITION BY RANGE (block_number);
+
+ALTER SEQUENCE device_events_id_seq OWNED BY device_events.id;
+
+SELECT ensure_block_partition('device_events', block_number, 1000000)
+FROM (SELECT DISTINCT block_number / 1000000 * 1000000 AS block_number FROM device_events_unpartitioned) b;
+
+INSERT INTO device_events (
+    id, device_id, owner_address, event_type, device_type, zone,
+    timestamp, block_number, tx_hash, created_at
+)
+SELECT
+    id, device_id, owner_address, event_type, device_type, zone,
+    timestamp, block_number, tx_hash, created_at
+FROM device_events_unpartitioned;
+
+DROP TABLE device_events_unpartitioned;
+
+ALTER TABLE device_events ADD PRIMARY KEY (id, block_number);
+CREATE INDEX idx_device_events_device_id ON device_events(device_id);
+CREATE INDEX idx_device_events_owner ON device_events(owner_address);
+CREATE INDEX idx_device_events_timestamp ON device_events(timestamp);
+CREATE INDEX idx_device_events_block ON device_events(block_number);
+
+CREATE TRIGGER trg_device_events_stats
+    AFTER INSERT ON device_events
+    FOR EACH ROW EXECUTE FUNCTION bump_stats_counters('device_events');
+
+CREATE TRIGGER trg_device_events_notify
+    AFTER INSERT ON device_events
+    FOR EACH ROW EXECUTE FUNCTION notify_indexed_event('device_events');
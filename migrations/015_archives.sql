@@ -0,0 +1,18 @@
+-- Manifest of rows moved out of the database by the retention job
+--
+-- Each entry is one Parquet file holding `row_count` rows of `table_name`,
+-- deleted from the table in the same transaction the entry was recorded in.
+
+CREATE TABLE IF NOT EXISTS archives (
+    id BIGSERIAL PRIMARY KEY,
+    table_name VARCHAR(50) NOT NULL,
+    path TEXT NOT NULL,
+    row_count BIGINT NOT NULL,
+    from_block BIGINT NOT NULL,
+    to_block BIGINT NOT NULL,
+    from_timestamp BIGINT NOT NULL,
+    to_timestamp BIGINT NOT NULL,
+    archived_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_archives_table_block ON archives(table_name, from_block);
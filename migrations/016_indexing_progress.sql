@@ -0,0 +1,10 @@
+-- Last block indexed per contract, as the primary last recorded it
+--
+-- Written after the rows for those blocks have committed, so a replica that
+-- has replayed an entry has replayed every row up to its block as well.
+
+CREATE TABLE IF NOT EXISTS indexing_progress (
+    contract VARCHAR(50) PRIMARY KEY,
+    indexed_block BIGINT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
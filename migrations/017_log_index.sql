@@ -0,0 +1,23 @@
+-- Identify every indexed row by the log it came from, so a log delivered
+-- more than once (a backfill overlapping the live subscription, a
+-- reconnect, a replayed failed event) is stored, streamed, delivered and
+-- counted once.
+--
+-- Rows indexed before this have no log index. They keep NULL, which the
+-- unique indexes treat as distinct from everything.
+
+ALTER TABLE verifier_events ADD COLUMN log_index BIGINT;
+ALTER TABLE device_events ADD COLUMN log_index BIGINT;
+ALTER TABLE device_transfers ADD COLUMN log_index BIGINT;
+ALTER TABLE data_submissions ADD COLUMN log_index BIGINT;
+ALTER TABLE marketplace_config ADD COLUMN log_index BIGINT;
+ALTER TABLE ownership_transfers ADD COLUMN log_index BIGINT;
+
+CREATE UNIQUE INDEX idx_verifier_events_log ON verifier_events(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_device_transfers_log ON device_transfers(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_marketplace_config_log ON marketplace_config(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_ownership_transfers_log ON ownership_transfers(tx_hash, log_index);
+
+-- The partition key has to be part of every unique index
+CREATE UNIQUE INDEX idx_device_events_log ON device_events(tx_hash, log_index, block_number);
+CREATE UNIQUE INDEX idx_data_submissions_log ON data_submissions(tx_hash, log_index, block_number);
@@ -0,0 +1,65 @@
+-- Count devices and verifiers the way the seed query in 002 does
+--
+-- The counters used to move on every event: a repeated registration counted
+-- a device twice, and a repeated add or remove moved the verifier count even
+-- though the verifier's state did not change. They now move only when an
+-- insert changes the answer: a device's first registration, or a verifier
+-- whose latest event (by block and id) flips between added and removed.
+-- "Before" is taken over the rows inserted ahead of this one (lower id), so
+-- a statement inserting several rows, whose rows all see each other by the
+-- time the triggers fire, counts the same as separate inserts.
+
+CREATE OR REPLACE FUNCTION bump_stats_counters() RETURNS TRIGGER AS $$
+DECLARE
+    source TEXT := COALESCE(TG_ARGV[0], TG_TABLE_NAME);
+    was_added BOOLEAN;
+    is_added BOOLEAN;
+BEGIN
+    IF source = 'data_submissions' THEN
+        UPDATE stats_counters SET value = value + 1 WHERE name = 'data_submissions';
+    ELSIF source = 'device_events' AND NEW.event_type = 'registered' THEN
+        IF NOT EXISTS (
+            SELECT 1 FROM device_events
+            WHERE device_id = NEW.device_id AND event_type = 'registered' AND id < NEW.id
+        ) THEN
+            UPDATE stats_counters SET value = value + 1 WHERE name = 'devices';
+        END IF;
+    ELSIF source = 'verifier_events' THEN
+        SELECT event_type = 'added' INTO was_added
+        FROM verifier_events
+        WHERE verifier_address = NEW.verifier_address AND id < NEW.id
+        ORDER BY block_number DESC, id DESC
+        LIMIT 1;
+
+        SELECT event_type = 'added' INTO is_added
+        FROM verifier_events
+        WHERE verifier_address = NEW.verifier_address AND id <= NEW.id
+        ORDER BY block_number DESC, id DESC
+        LIMIT 1;
+
+        IF COALESCE(was_added, FALSE) <> is_added THEN
+            UPDATE stats_counters
+            SET value = GREATEST(value + CASE WHEN is_added THEN 1 ELSE -1 END, 0)
+            WHERE name = 'verifiers';
+        END IF;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Correct the drift so far. data_submissions is left alone: it keeps
+-- counting submissions that retention has since archived
+UPDATE stats_counters
+SET value = (SELECT COUNT(DISTINCT device_id) FROM device_events WHERE event_type = 'registered')
+WHERE name = 'devices';
+
+UPDATE stats_counters
+SET value = (
+    SELECT COUNT(*) FROM (
+        SELECT DISTINCT ON (verifier_address) event_type
+        FROM verifier_events
+        ORDER BY verifier_address, block_number DESC, id DESC
+    ) latest
+    WHERE event_type = 'added'
+)
+WHERE name = 'verifiers';
@@ -0,0 +1,24 @@
+-- Store failed_events.tx_hash as bytes like every other transaction hash
+--
+-- Logs are keyed without NULLs, which a unique index never matches: a log
+-- the node reported without a transaction hash or log index is recorded
+-- under the zero hash and index 0, as the indexer stores it, so failing on
+-- it again counts as another attempt rather than a new entry.
+
+DROP INDEX idx_failed_events_log;
+
+ALTER TABLE failed_events
+    ALTER COLUMN tx_hash TYPE BYTEA
+        USING decode(substr(COALESCE(tx_hash, '0x' || repeat('0', 64)), 3), 'hex');
+
+UPDATE failed_events SET log_index = 0 WHERE log_index IS NULL;
+
+ALTER TABLE failed_events
+    ALTER COLUMN tx_hash SET NOT NULL,
+    ALTER COLUMN log_index SET NOT NULL;
+
+DELETE FROM failed_events a
+USING failed_events b
+WHERE a.contract = b.contract AND a.tx_hash = b.tx_hash AND a.log_index = b.log_index AND a.id < b.id;
+
+CREATE UNIQUE INDEX idx_failed_events_log ON failed_events(contract, tx_hash, log_index);
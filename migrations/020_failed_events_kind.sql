@@ -0,0 +1,9 @@
+-- Tell logs holding a value out of range for its column apart from other
+-- failures: retrying them cannot succeed, so they are listed separately
+-- and refused by the retry endpoint.
+
+ALTER TABLE failed_events ADD COLUMN kind TEXT NOT NULL DEFAULT 'error';
+
+UPDATE failed_events SET kind = 'out_of_range' WHERE error LIKE '%does not fit in a BIGINT column%';
+
+CREATE INDEX idx_failed_events_kind ON failed_events(kind, id);
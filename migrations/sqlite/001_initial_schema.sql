@@ -0,0 +1,118 @@
+-- Schema for the SQLite backend
+--
+-- The event tables of the PostgreSQL schema, with hashes, device IDs and
+-- addresses as BLOBs and timestamps as RFC 3339 text. Counters, liveness and
+-- activity are computed from these tables rather than kept by triggers, and
+-- the stream, webhook, alert, rollup and archive tables are PostgreSQL-only.
+
+CREATE TABLE IF NOT EXISTS verifier_events (
+    id INTEGER PRIMARY KEY,
+    verifier_address BLOB NOT NULL,
+    event_type TEXT NOT NULL CHECK (event_type IN ('added', 'removed')),
+    timestamp INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_verifier_events_address ON verifier_events(verifier_address);
+CREATE INDEX idx_verifier_events_timestamp ON verifier_events(timestamp);
+CREATE INDEX idx_verifier_events_block ON verifier_events(block_number);
+
+CREATE TABLE IF NOT EXISTS device_events (
+    id INTEGER PRIMARY KEY,
+    device_id BLOB NOT NULL,
+    owner_address BLOB NOT NULL,
+    event_type TEXT NOT NULL CHECK (event_type IN ('registered', 'updated', 'transferred')),
+    device_type INTEGER,
+    zone TEXT,
+    timestamp INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_device_events_device_id ON device_events(device_id);
+CREATE INDEX idx_device_events_owner ON device_events(owner_address);
+CREATE INDEX idx_device_events_timestamp ON device_events(timestamp);
+CREATE INDEX idx_device_events_block ON device_events(block_number);
+
+CREATE TABLE IF NOT EXISTS device_transfers (
+    id INTEGER PRIMARY KEY,
+    device_id BLOB NOT NULL,
+    old_owner BLOB NOT NULL,
+    new_owner BLOB NOT NULL,
+    timestamp INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_device_transfers_device_id ON device_transfers(device_id);
+CREATE INDEX idx_device_transfers_old_owner ON device_transfers(old_owner);
+CREATE INDEX idx_device_transfers_new_owner ON device_transfers(new_owner);
+
+CREATE TABLE IF NOT EXISTS data_submissions (
+    id INTEGER PRIMARY KEY,
+    data_hash BLOB NOT NULL,
+    device_id_hash BLOB NOT NULL,
+    device_owner BLOB NOT NULL,
+    timestamp INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_data_submissions_data_hash ON data_submissions(data_hash);
+CREATE INDEX idx_data_submissions_device_hash ON data_submissions(device_id_hash, timestamp);
+CREATE INDEX idx_data_submissions_owner ON data_submissions(device_owner);
+CREATE INDEX idx_data_submissions_timestamp ON data_submissions(timestamp);
+
+-- base_fee is a uint256 kept as its decimal string
+CREATE TABLE IF NOT EXISTS marketplace_config (
+    id INTEGER PRIMARY KEY,
+    base_fee TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_marketplace_config_block ON marketplace_config(block_number, id);
+
+CREATE TABLE IF NOT EXISTS ownership_transfers (
+    id INTEGER PRIMARY KEY,
+    contract_type TEXT NOT NULL,
+    previous_owner BLOB NOT NULL,
+    new_owner BLOB NOT NULL,
+    block_number INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_ownership_transfers_contract ON ownership_transfers(contract_type);
+CREATE INDEX idx_ownership_transfers_prev_owner ON ownership_transfers(previous_owner);
+CREATE INDEX idx_ownership_transfers_new_owner ON ownership_transfers(new_owner);
+
+-- The deviceIdHash the IoT pipeline reports for each registered device
+CREATE TABLE IF NOT EXISTS device_id_hashes (
+    device_id_hash BLOB PRIMARY KEY,
+    device_id BLOB NOT NULL UNIQUE
+);
+
+-- block_number and log_index are kept as reported by the node, in decimal
+CREATE TABLE IF NOT EXISTS failed_events (
+    id INTEGER PRIMARY KEY,
+    contract TEXT NOT NULL,
+    topic TEXT,
+    block_number TEXT,
+    log_index TEXT,
+    tx_hash TEXT,
+    error TEXT NOT NULL,
+    raw_log TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_attempt_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX idx_failed_events_contract ON failed_events(contract, id);
+CREATE UNIQUE INDEX idx_failed_events_log ON failed_events(contract, tx_hash, log_index);
@@ -0,0 +1,17 @@
+-- Identify every indexed row by the log it came from, so a log delivered
+-- more than once is stored once. Rows indexed before this keep a NULL log
+-- index, which the unique indexes treat as distinct from everything.
+
+ALTER TABLE verifier_events ADD COLUMN log_index INTEGER;
+ALTER TABLE device_events ADD COLUMN log_index INTEGER;
+ALTER TABLE device_transfers ADD COLUMN log_index INTEGER;
+ALTER TABLE data_submissions ADD COLUMN log_index INTEGER;
+ALTER TABLE marketplace_config ADD COLUMN log_index INTEGER;
+ALTER TABLE ownership_transfers ADD COLUMN log_index INTEGER;
+
+CREATE UNIQUE INDEX idx_verifier_events_log ON verifier_events(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_device_events_log ON device_events(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_device_transfers_log ON device_transfers(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_data_submissions_log ON data_submissions(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_marketplace_config_log ON marketplace_config(tx_hash, log_index);
+CREATE UNIQUE INDEX idx_ownership_transfers_log ON ownership_transfers(tx_hash, log_index);
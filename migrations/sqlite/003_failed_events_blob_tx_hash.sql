@@ -0,0 +1,38 @@
+-- Store failed_events.tx_hash as bytes like every other transaction hash
+--
+-- Logs are keyed without NULLs, which a unique index never matches: a log
+-- the node reported without a transaction hash or log index is recorded
+-- under the zero hash and index 0, as the indexer stores it. SQLite cannot
+-- change a column's type, so the table is rebuilt, keeping the latest entry
+-- for each log.
+
+CREATE TABLE failed_events_new (
+    id INTEGER PRIMARY KEY,
+    contract TEXT NOT NULL,
+    topic TEXT,
+    block_number TEXT,
+    log_index TEXT NOT NULL,
+    tx_hash BLOB NOT NULL,
+    error TEXT NOT NULL,
+    raw_log TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_attempt_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+INSERT INTO failed_events_new (
+    id, contract, topic, block_number, log_index, tx_hash,
+    error, raw_log, attempts, last_attempt_at, created_at
+)
+SELECT
+    MAX(id), contract, topic, block_number, COALESCE(log_index, '0'),
+    COALESCE(unhex(substr(tx_hash, 3)), zeroblob(32)),
+    error, raw_log, attempts, last_attempt_at, created_at
+FROM failed_events
+GROUP BY contract, COALESCE(unhex(substr(tx_hash, 3)), zeroblob(32)), COALESCE(log_index, '0');
+
+DROP TABLE failed_events;
+ALTER TABLE failed_events_new RENAME TO failed_events;
+
+CREATE INDEX idx_failed_events_contract ON failed_events(contract, id);
+CREATE UNIQUE INDEX idx_failed_events_log ON failed_events(contract, tx_hash, log_index);
@@ -0,0 +1,7 @@
+-- Last block indexed per contract, so a restarted indexer resumes from it
+
+CREATE TABLE IF NOT EXISTS indexing_progress (
+    contract TEXT PRIMARY KEY,
+    indexed_block INTEGER NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
@@ -0,0 +1,255 @@
+//! Submission-rate anomaly alerts
+//!
+//! A background job compares the submissions each device, owner and zone
+//! made in the last complete hour with the mean and standard deviation of
+//! the hours before it, read from `submission_rollups`. Counts far outside
+//! that baseline are recorded in `alerts` and, when an alert webhook is
+//! configured, POSTed to it until it accepts them.
+
+use crate::{
+    analytics::REGISTERED_DEVICES_JOIN,
+    config::Config,
+    models::{Alert, AlertKind, AlertScope, TimeBucket},
+    status,
+    webhooks::{sign, EVENT_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    AppState,
+};
+use chrono::Utc;
+use sqlx::{FromRow, Pool, Postgres};
+use std::{sync::Arc, time::Duration};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// `X-Lcore-Event` value on forwarded alerts
+const ALERT_EVENT: &str = "Alert";
+
+/// Time allowed for the alert webhook to answer
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Alerts forwarded per round
+const FORWARD_BATCH: i64 = 100;
+
+/// SELECT list for `Alert`, with enum columns cast to text
+pub(crate) const ALERT_COLUMNS: &str = r#"
+    id, scope::text AS scope, subject, kind::text AS kind, window_start, observed,
+    baseline_mean, baseline_stddev, forwarded_at, created_at
+"#;
+
+/// Submissions by one subject in the checked hour, against its baseline
+#[derive(FromRow)]
+struct Rate {
+    subject: String,
+    observed: i64,
+    mean: f64,
+    stddev: f64,
+}
+
+/// Check submission rates and forward alerts until the process exits
+pub async fn run_detector(state: Arc<AppState>, db: Pool<Postgres>) {
+    let client = match reqwest::Client::builder().timeout(FORWARD_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Alert detector failed to start: {}", e);
+            return;
+        }
+    };
+    let period = Duration::from_secs(state.config.alert_check_interval_secs);
+    let mut ticks = tokio::time::interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    
+    loop {
+        ticks.tick().await;
+    
+        // While catching up, recent hours are not fully indexed yet and
+        // would read as drops
+        if caught_up(&state).await {
+            if let Err(e) = detect(&db, &state.config, Utc::now().timestamp()).await {
+                warn!("Alert detection failed: {}", e);
+            }
+        }
+    
+        if let Some(url) = &state.config.alert_webhook_url {
+            let secret = state.config.alert_webhook_secret.as_deref();
+            if let Err(e) = forward_pending(&db, &client, url, secret).await {
+                warn!("Alert forwarding failed: {}", e);
+            }
+        }
+    }
+}
+
+async fn caught_up(state: &AppState) -> bool {
+    let status = state.status.read().await;
+    status
+        .contracts
+        .get(status::IOT_PIPELINE)
+        .is_some_and(|p| p.live && status.lag_blocks(p) <= state.config.max_lag_blocks)
+}
+
+/// Raise alerts for the last complete hour before `now`
+async fn detect(db: &Pool<Postgres>, config: &Config, now: i64) -> sqlx::Result<()> {
+    let hour = TimeBucket::Hour.width();
+    let window_start = TimeBucket::Hour.floor(now) - hour;
+    let baseline_start = window_start - i64::from(config.alert_baseline_hours) * hour;
+    
+    for scope in [AlertScope::Device, AlertScope::Owner, AlertScope::Zone] {
+        for rate in rates(db, scope, baseline_start, window_start).await? {
+            let Some(kind) = classify(config, &rate) else {
+                continue;
+            };
+    
+            let raised: Option<i64> = sqlx::query_scalar(
+                r#"
+                INSERT INTO alerts (
+                    scope, subject, kind, window_start, observed, baseline_mean, baseline_stddev
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT DO NOTHING
+                RETURNING id
+                "#
+            )
+            .bind(scope)
+            .bind(&rate.subject)
+            .bind(kind)
+            .bind(window_start)
+            .bind(rate.observed)
+            .bind(rate.mean)
+            .bind(rate.stddev)
+            .fetch_optional(db)
+            .await?;
+    
+            if raised.is_some() {
+                info!(
+                    "{:?} alert for {} {}: {} submissions in the hour against a mean of {:.1}",
+                    kind, scope.as_str(), rate.subject, rate.observed, rate.mean
+                );
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Hourly submissions per subject of `scope`, with the hours from
+/// `baseline_start` up to `window_start` as the baseline. Subjects silent for
+/// the whole period are left out; missing hours count as zero.
+async fn rates(
+    db: &Pool<Postgres>,
+    scope: AlertScope,
+    baseline_start: i64,
+    window_start: i64,
+) -> sqlx::Result<Vec<Rate>> {
+    let (subject, devices) = match scope {
+        AlertScope::Device => (
+            "'0x' || encode(COALESCE(h.device_id, r.device_id_hash), 'hex')",
+            REGISTERED_DEVICES_JOIN,
+        ),
+        AlertScope::Owner => ("'0x' || encode(r.device_owner, 'hex')", ""),
+        AlertScope::Zone => ("d.zone", REGISTERED_DEVICES_JOIN),
+    };
+    
+    sqlx::query_as::<_, Rate>(&format!(
+        r#"
+        WITH counts AS (
+            SELECT {subject} AS subject, r.hour_start, SUM(r.submissions) AS n
+            FROM submission_rollups r
+            {devices}
+            WHERE r.hour_start BETWEEN $1 AND $2 AND {subject} IS NOT NULL
+            GROUP BY 1, 2
+        ),
+        filled AS (
+            SELECT s.subject, hours.hour_start, COALESCE(c.n, 0) AS n
+            FROM (SELECT DISTINCT subject FROM counts) s
+            CROSS JOIN generate_series($1::BIGINT, $2::BIGINT, $3::BIGINT) AS hours(hour_start)
+            LEFT JOIN counts c ON c.subject = s.subject AND c.hour_start = hours.hour_start
+        )
+        SELECT
+            subject,
+            SUM(n) FILTER (WHERE hour_start = $2)::BIGINT AS observed,
+            AVG(n) FILTER (WHERE hour_start < $2)::FLOAT8 AS mean,
+            STDDEV_POP(n) FILTER (WHERE hour_start < $2)::FLOAT8 AS stddev
+        FROM filled
+        GROUP BY subject
+        "#
+    ))
+    .bind(baseline_start)
+    .bind(window_start)
+    .bind(TimeBucket::Hour.width())
+    .fetch_all(db)
+    .await
+}
+
+fn classify(config: &Config, rate: &Rate) -> Option<AlertKind> {
+    // A perfectly steady baseline would put any change infinitely far out
+    let spread = config.alert_threshold * rate.stddev.max(1.0);
+    let min = config.alert_min_submissions as f64;
+    let observed = rate.observed as f64;
+    
+    if observed >= min && observed > rate.mean + spread {
+        Some(AlertKind::Spike)
+    } else if rate.mean >= min && observed < rate.mean - spread {
+        Some(AlertKind::Drop)
+    } else {
+        None
+    }
+}
+
+/// POST recent alerts the webhook has not accepted yet, oldest first
+async fn forward_pending(
+    db: &Pool<Postgres>,
+    client: &reqwest::Client,
+    url: &str,
+    secret: Option<&str>,
+) -> sqlx::Result<()> {
+    let pending = sqlx::query_as::<_, Alert>(&format!(
+        r#"
+        SELECT {ALERT_COLUMNS} FROM alerts
+        WHERE forwarded_at IS NULL AND created_at > NOW() - INTERVAL '1 day'
+        ORDER BY id
+        LIMIT $1
+        "#
+    ))
+    .bind(FORWARD_BATCH)
+    .fetch_all(db)
+    .await?;
+    
+    for alert in pending.into_iter().map(Alert::checksummed) {
+        let body = match serde_json::to_vec(&alert) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to encode alert {}: {}", alert.id, e);
+                continue;
+            }
+        };
+        let timestamp = Utc::now().timestamp();
+    
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, ALERT_EVENT)
+            .header(TIMESTAMP_HEADER, timestamp);
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, timestamp, &body));
+        }
+    
+        // Leave the rest for the next round rather than wait out a dead
+        // endpoint once per alert
+        match request.body(body).send().await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => {
+                warn!("Alert webhook answered {} for alert {}", response.status(), alert.id);
+                break;
+            }
+            Err(e) => {
+                warn!("Alert webhook unreachable for alert {}: {}", alert.id, e);
+                break;
+            }
+        }
+    
+        sqlx::query("UPDATE alerts SET forwarded_at = NOW() WHERE id = $1")
+            .bind(alert.id)
+            .execute(db)
+            .await?;
+    }
+    
+    Ok(())
+}
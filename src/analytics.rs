@@ -0,0 +1,209 @@
+//! Time-series aggregates over data submissions
+//!
+//! Counts are read from `submission_rollups`, which a trigger keeps at one
+//! row per device, owner and hour, so a query scans rollup rows rather than
+//! every submission in the range.
+
+use crate::{
+    api::device_id_hash,
+    models::{
+        DeviceTypeStats, HexAddress, HexBytes32, SubmissionBucket, SubmissionGrouping, TimeBucket, ZoneStats,
+    },
+    storage::sql::DEVICE_STATE_CTE,
+};
+use sqlx::{PgExecutor, Pool, Postgres};
+use tracing::info;
+
+const HOUR_SECS: i64 = 60 * 60;
+const DAY_SECS: i64 = 24 * HOUR_SECS;
+const WEEK_SECS: i64 = 7 * DAY_SECS;
+
+/// The Unix epoch fell on a Thursday; weeks start on the following Monday
+const WEEK_ORIGIN: i64 = 4 * DAY_SECS;
+
+impl TimeBucket {
+    pub fn width(&self) -> i64 {
+        match self {
+            TimeBucket::Hour => HOUR_SECS,
+            TimeBucket::Day => DAY_SECS,
+            TimeBucket::Week => WEEK_SECS,
+        }
+    }
+    
+    fn origin(&self) -> i64 {
+        match self {
+            TimeBucket::Week => WEEK_ORIGIN,
+            _ => 0,
+        }
+    }
+    
+    /// Start of the bucket containing `timestamp`
+    pub fn floor(&self, timestamp: i64) -> i64 {
+        timestamp - (timestamp - self.origin()).rem_euclid(self.width())
+    }
+    
+    /// Range covered when the caller gives no `from`
+    pub fn default_span(&self) -> i64 {
+        match self {
+            TimeBucket::Hour => 2 * DAY_SECS,
+            TimeBucket::Day => 30 * DAY_SECS,
+            TimeBucket::Week => 26 * WEEK_SECS,
+        }
+    }
+}
+
+/// Submission counts per bucket (and group) for buckets starting in
+/// `[from, to)`; both bounds must be bucket-aligned
+pub async fn submission_series(
+    db: &Pool<Postgres>,
+    bucket: TimeBucket,
+    group_by: Option<SubmissionGrouping>,
+    from: i64,
+    to: i64,
+) -> sqlx::Result<Vec<SubmissionBucket>> {
+    let (group, devices) = match group_by {
+        None => ("NULL::TEXT", ""),
+        Some(SubmissionGrouping::Owner) => ("'0x' || encode(r.device_owner, 'hex')", ""),
+        Some(SubmissionGrouping::Zone) => ("d.zone::TEXT", REGISTERED_DEVICES_JOIN),
+        Some(SubmissionGrouping::DeviceType) => ("d.device_type::TEXT", REGISTERED_DEVICES_JOIN),
+    };
+    
+    let mut buckets = sqlx::query_as::<_, SubmissionBucket>(&format!(
+        r#"
+        SELECT
+            r.hour_start - (r.hour_start - $3) % $4 AS bucket_start,
+            {group} AS "group",
+            SUM(r.submissions)::BIGINT AS submissions
+        FROM submission_rollups r
+        {devices}
+        WHERE r.hour_start >= $1 AND r.hour_start < $2
+        GROUP BY 1, 2
+        ORDER BY 1, 2 NULLS LAST
+        "#
+    ))
+    .bind(from)
+    .bind(to)
+    .bind(bucket.origin())
+    .bind(bucket.width())
+    .fetch_all(db)
+    .await?;
+    
+    // Owners are served with their EIP-55 checksum, like other addresses
+    if group_by == Some(SubmissionGrouping::Owner) {
+        for bucket in &mut buckets {
+            if let Some(owner) = bucket.group.as_ref().and_then(|g| g.parse::<HexAddress>().ok()) {
+                bucket.group = Some(owner.checksummed());
+            }
+        }
+    }
+    
+    Ok(buckets)
+}
+
+/// Resolves rollup rows to the zone and device type the device registered with
+pub(crate) const REGISTERED_DEVICES_JOIN: &str = r#"
+    LEFT JOIN device_id_hashes h ON h.device_id_hash = r.device_id_hash
+    LEFT JOIN (
+        SELECT DISTINCT ON (device_id) device_id, zone, device_type
+        FROM device_events
+        WHERE event_type = 'registered'
+        ORDER BY device_id, block_number, id
+    ) d ON d.device_id = h.device_id
+"#;
+
+/// Appended to `DEVICE_STATE_CTE`: submissions per device in rollup hours
+/// starting at or after `$2`
+const DEVICE_ACTIVITY_CTE: &str = r#"
+    , activity AS (
+        SELECT h.device_id, SUM(r.submissions) AS submissions
+        FROM submission_rollups r
+        JOIN device_id_hashes h ON h.device_id_hash = r.device_id_hash
+        WHERE r.hour_start >= $2
+        GROUP BY h.device_id
+    )
+"#;
+
+/// Per-zone device counts, with activity in the hours starting at or after `since`
+pub async fn zone_stats(db: &Pool<Postgres>, since: i64) -> sqlx::Result<Vec<ZoneStats>> {
+    sqlx::query_as::<_, ZoneStats>(&format!(
+        r#"
+        {DEVICE_STATE_CTE} {DEVICE_ACTIVITY_CTE}
+        SELECT
+            d.zone,
+            COUNT(*) AS device_count,
+            COUNT(a.device_id) AS active_devices,
+            COALESCE(SUM(a.submissions), 0)::BIGINT AS submissions
+        FROM devices d
+        LEFT JOIN activity a ON a.device_id = d.device_id
+        WHERE d.zone IS NOT NULL
+        GROUP BY d.zone
+        ORDER BY d.zone
+        "#
+    ))
+    .bind(None::<i64>)
+    .bind(since)
+    .fetch_all(db)
+    .await
+}
+
+/// Per-device-type device counts, with activity in the hours starting at or
+/// after `since`
+pub async fn device_type_stats(db: &Pool<Postgres>, since: i64) -> sqlx::Result<Vec<DeviceTypeStats>> {
+    sqlx::query_as::<_, DeviceTypeStats>(&format!(
+        r#"
+        {DEVICE_STATE_CTE} {DEVICE_ACTIVITY_CTE}
+        SELECT
+            d.device_type,
+            COUNT(*) AS device_count,
+            COUNT(a.device_id) AS active_devices,
+            COALESCE(SUM(a.submissions), 0)::BIGINT AS submissions
+        FROM devices d
+        LEFT JOIN activity a ON a.device_id = d.device_id
+        WHERE d.device_type IS NOT NULL
+        GROUP BY d.device_type
+        ORDER BY d.device_type
+        "#
+    ))
+    .bind(None::<i64>)
+    .bind(since)
+    .fetch_all(db)
+    .await
+}
+
+/// Remember the `deviceIdHash` the IoT pipeline will report for `device_id`
+pub async fn record_device_hash(db: impl PgExecutor<'_>, device_id: &HexBytes32) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO device_id_hashes (device_id_hash, device_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"
+    )
+    .bind(device_id_hash(device_id))
+    .bind(device_id)
+    .execute(db)
+    .await?;
+    
+    Ok(())
+}
+
+/// Map every registered device indexed before `device_id_hashes` existed
+pub async fn backfill_device_hashes(db: &Pool<Postgres>) -> sqlx::Result<()> {
+    // IDs that are not 32 bytes can never be reported by the pipeline
+    let device_ids: Vec<HexBytes32> = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT e.device_id
+        FROM device_events e
+        WHERE e.event_type = 'registered'
+            AND octet_length(e.device_id) = 32
+            AND NOT EXISTS (SELECT 1 FROM device_id_hashes h WHERE h.device_id = e.device_id)
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+    
+    for device_id in &device_ids {
+        record_device_hash(db, device_id).await?;
+    }
+    if !device_ids.is_empty() {
+        info!("Mapped device ID hashes for {} devices", device_ids.len());
+    }
+    
+    Ok(())
+}
@@ -1,27 +1,163 @@
 //! REST API for querying indexed events
 
-use crate::{error::ApiError, models::*, AppState};
+use crate::{
+    alerts::ALERT_COLUMNS,
+    analytics,
+    config::DeviceTypeNames,
+    error::{ApiError, ErrorResponse},
+    export::{self, BlockRange, ExportFormat, ExportTable},
+    graphql::{self, IndexerSchema},
+    liveness::StaleCutoffs,
+    openapi::ApiDoc,
+    models::*,
+    storage::{DeviceFilter, Paging},
+    stream::{self, StreamFilter},
+    webhooks,
+    AppState,
+};
 use axum::{
-    extract::{Path, Query, State},
-    response::Json,
-    routing::get,
-    Router,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        MatchedPath, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Json, Response,
+    },
+    routing::{delete, get, post},
+    Extension, Router,
     serve,
 };
+use chrono::Utc;
+use ethers::{core::types::Log, providers::Middleware};
 use serde::{Deserialize, Serialize};
-use sqlx::Row;
-use std::{net::SocketAddr, sync::Arc};
+use sqlx::{Pool, Postgres};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tower_http::cors::CorsLayer;
 use tracing::info;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Upper bound on each dependency check made by `/ready`
+const READY_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most buckets one `/analytics/submissions` request may span
+const MAX_ANALYTICS_BUCKETS: i64 = 5000;
+
+/// Longest activity window `/zones` and `/device-types` accept
+const MAX_ACTIVITY_HOURS: u32 = 366 * 24;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamQuery {
+    pub contract: Option<String>,
+    pub event_type: Option<String>,
+    pub owner: Option<HexAddress>,
+    /// Device ID, or device ID hash for data submissions
+    pub device: Option<HexBytes32>,
+    /// Resume after this event id; the `Last-Event-ID` header takes precedence
+    pub cursor: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Output format (defaults to csv)
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// First block to include
+    pub from_block: Option<i64>,
+    /// Last block to include
+    pub to_block: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubmissionAnalyticsQuery {
+    /// Bucket width (defaults to hour)
+    #[serde(default)]
+    pub bucket: TimeBucket,
+    /// Split each bucket's count by this dimension
+    pub group_by: Option<SubmissionGrouping>,
+    /// Unix time to start from (defaults to 2 days, 30 days or 26 weeks before `to`)
+    pub from: Option<i64>,
+    /// Unix time to end at, exclusive (defaults to now)
+    pub to: Option<i64>,
+}
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeviceStatusQuery {
+    /// Only devices in this reporting state
+    pub status: Option<DeviceStatus>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AlertQuery {
+    pub scope: Option<AlertScope>,
+    pub kind: Option<AlertKind>,
+    /// Device ID, owner address or zone
+    pub subject: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ActivityQuery {
+    /// Hours of activity counted, in whole rollup hours (defaults to 24)
+    #[serde(default = "default_activity_hours")]
+    pub hours: u32,
+}
+
+fn default_activity_hours() -> u32 {
+    24
+}
+
+impl ActivityQuery {
+    /// Start of the earliest rollup hour in the window
+    fn since(&self) -> Result<i64, ApiError> {
+        if !(1..=MAX_ACTIVITY_HOURS).contains(&self.hours) {
+            return Err(ApiError::BadRequest(format!(
+                "hours must be between 1 and {}",
+                MAX_ACTIVITY_HOURS
+            )));
+        }
+        
+        let now = TimeBucket::Hour.floor(Utc::now().timestamp());
+        Ok(now - (self.hours as i64 - 1) * TimeBucket::Hour.width())
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PaginationQuery {
+    /// Page number, starting at 1
     #[serde(default = "default_page")]
     pub page: u32,
+    /// Items per page
     #[serde(default = "default_limit")]
     pub limit: u32,
 }
 
+impl PaginationQuery {
+    fn paging(&self) -> Result<Paging, ApiError> {
+        if self.page == 0 {
+            return Err(ApiError::BadRequest("page starts at 1".to_string()));
+        }
+        Ok(Paging {
+            limit: i64::from(self.limit),
+            offset: i64::from(self.page - 1) * i64::from(self.limit),
+        })
+    }
+}
+
 fn default_page() -> u32 {
     1
 }
@@ -30,27 +166,143 @@ fn default_limit() -> u32 {
     20
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PaginatedResponse<T> {
     pub data: Vec<T>,
     pub page: u32,
     pub limit: u32,
     pub total: i64,
+    /// Present when rows of the queried table up to `as_of_block` have been
+    /// archived by the retention job, and so are missing from `data` and `total`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<ArchivedRows>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AsOfQuery {
+    /// Only consider events up to and including this block
+    pub as_of_block: Option<i64>,
+}
+
+impl AsOfQuery {
+    fn block(&self) -> Result<Option<i64>, ApiError> {
+        match self.as_of_block {
+            Some(block) if block < 0 => Err(ApiError::BadRequest(
+                "as_of_block must not be negative".to_string(),
+            )),
+            block => Ok(block),
+        }
+    }
+    
+    /// Refuse `as_of_block` on endpoints that only report the current state,
+    /// rather than silently answering for the head
+    fn current_only(&self) -> Result<(), ApiError> {
+        match self.as_of_block {
+            Some(_) => Err(ApiError::BadRequest(
+                "as_of_block is not supported by this endpoint".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The PostgreSQL pool, for endpoints the SQLite backend does not provide
+fn postgres(state: &AppState) -> Result<&Pool<Postgres>, ApiError> {
+    state.db.as_ref().ok_or_else(unsupported)
+}
+
+/// The PostgreSQL read pool, for read-only endpoints the SQLite backend does
+/// not provide
+fn postgres_reads(state: &AppState) -> Result<&Pool<Postgres>, ApiError> {
+    state.read_db.as_ref().ok_or_else(unsupported)
+}
+
+fn unsupported() -> ApiError {
+    ApiError::Unsupported("This endpoint requires the PostgreSQL backend".to_string())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Event types to deliver; omitted or empty means every type
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    /// Key for the HMAC-SHA256 signature sent with each delivery
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FailedEventQuery {
+    /// verifier_registry, device_registry or iot_pipeline
+    pub contract: Option<String>,
+    pub kind: Option<FailureKind>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VerifierQuery {
+    /// Only return verifiers that are (or are not) active
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OwnerAtQuery {
+    /// Block to resolve the owner at (defaults to the current owner)
+    pub block: Option<i64>,
+}
+
+/// The `deviceIdHash` the IoT pipeline reports for a registry device ID:
+/// keccak256 of the 32-byte ID
+pub(crate) fn device_id_hash(device_id: &HexBytes32) -> HexBytes32 {
+    HexBytes32::from(ethers::utils::keccak256(device_id.as_bytes()))
 }
 
 pub async fn run_server(state: Arc<AppState>) -> Result<(), ApiError> {
     let app = Router::new()
         .route("/health", get(health_check))
+        .route("/ready", get(readiness_check))
         .route("/stats", get(get_stats))
         .route("/verifiers", get(get_verifiers))
-        // TODO: Re-enable these endpoints after fixing SQLx macros
-        // .route("/verifiers/:address/events", get(get_verifier_events))
-        // .route("/devices", get(get_devices))
-        // .route("/devices/:id", get(get_device))
-        // .route("/devices/:id/events", get(get_device_events))
-        // .route("/devices/:id/data", get(get_device_data))
-        // .route("/data/recent", get(get_recent_data))
-        // .route("/ownership-transfers", get(get_ownership_transfers))
+        .route("/verifiers/:address/events", get(get_verifier_events))
+        .route("/devices", get(get_devices))
+        .route("/devices/:id", get(get_device))
+        .route("/devices/:id/events", get(get_device_events))
+        .route("/devices/:id/owners", get(get_device_owners))
+        .route("/devices/:id/owner", get(get_device_owner))
+        .route("/devices/:id/transfers", get(get_device_transfers))
+        .route("/devices/:id/data", get(get_device_data))
+        .route("/zones", get(get_zones))
+        .route("/zones/:zone/devices", get(get_zone_devices))
+        .route("/device-types", get(get_device_types))
+        .route("/data/recent", get(get_recent_data))
+        .route("/ownership-transfers", get(get_ownership_transfers))
+        .route("/marketplace/config", get(get_marketplace_config))
+        .route("/metrics", get(get_metrics))
+        .route("/stream/events", get(stream_events))
+        .route("/graphql", get(graphiql).post(graphql_handler))
+        .route("/export/:table", get(export_table))
+        .route("/analytics/submissions", get(get_submission_analytics))
+        .route("/alerts", get(get_alerts))
+        .route("/openapi.json", get(get_openapi))
+        .route("/docs", get(api_docs))
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_http_metrics));
+    let app = match &state.read_db {
+        Some(db) => app.layer(Extension(graphql::schema(
+            db.clone(),
+            state.config.device_type_names.clone(),
+        ))),
+        None => app,
+    };
+    let app = app
         .layer(CorsLayer::permissive())
         .with_state(state.clone());
     
@@ -67,349 +319,1524 @@ pub async fn run_server(state: Arc<AppState>) -> Result<(), ApiError> {
     Ok(())
 }
 
-async fn health_check(State(state): State<Arc<AppState>>) -> Result<Json<HealthResponse>, ApiError> {
+/// Serve the admin API, which manages webhooks and failed events, on its
+/// own listener and only to requests carrying `token`
+pub async fn run_admin_server(state: Arc<AppState>, token: String) -> Result<(), ApiError> {
+    let app = Router::new()
+        .route("/webhooks", get(get_webhooks).post(create_webhook))
+        .route("/webhooks/:id", get(get_webhook).delete(delete_webhook))
+        .route("/webhooks/:id/deliveries", get(get_webhook_deliveries))
+        .route("/webhooks/:id/deliveries/:delivery_id/retry", post(retry_webhook_delivery))
+        .route("/admin/failed-events", get(get_failed_events))
+        .route("/admin/failed-events/:id", delete(discard_failed_event))
+        .route("/admin/failed-events/:id/retry", post(retry_failed_event))
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_http_metrics))
+        .route_layer(middleware::from_fn_with_state(Arc::new(token), require_admin_token))
+        .with_state(state.clone());
+    
+    let addr = (state.config.admin_host.as_str(), state.config.admin_port);
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    info!("Admin API listening on {}:{}", addr.0, addr.1);
+    
+    serve(listener, app)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    
+    Ok(())
+}
+
+/// Reject requests without `Authorization: Bearer <admin_token>`
+async fn require_admin_token(
+    State(token): State<Arc<String>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+            Ok(next.run(request).await)
+        }
+        _ => Err(ApiError::Unauthorized("A valid admin bearer token is required".to_string())),
+    }
+}
+
+/// Compare secrets without returning early at the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Record request count and latency per matched route
+async fn track_http_metrics(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    
+    let started = Instant::now();
+    let response = next.run(request).await;
+    
+    state
+        .metrics
+        .http_duration
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    state
+        .metrics
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    
+    response
+}
+
+/// Push newly indexed events over Server-Sent Events, or over a WebSocket
+/// when the request asks for an upgrade
+#[utoipa::path(
+    get,
+    path = "/stream/events",
+    tag = "stream",
+    params(
+        StreamQuery,
+        ("Last-Event-ID" = Option<i64>, Header, description = "Resume after this event ID"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events, one `StreamEvent` per message", content_type = "text/event-stream", body = StreamEvent),
+        (status = 101, description = "Switched to a WebSocket sending one `StreamEvent` per text message"),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn stream_events(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+    upgrade: Option<WebSocketUpgrade>,
+) -> Result<Response, ApiError> {
+    let cursor = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .ok_or_else(|| ApiError::BadRequest("Last-Event-ID must be an event id".to_string()))?,
+        ),
+        None => query.cursor,
+    };
+    
+    let filter = StreamFilter {
+        contract: query.contract,
+        event_type: query.event_type,
+        owner: query.owner,
+        device: query.device,
+    };
+    let events = stream::follow(postgres(&state)?.clone(), &state.events, filter, cursor);
+    
+    if let Some(upgrade) = upgrade {
+        return Ok(upgrade.on_upgrade(move |socket| forward_to_socket(socket, events)));
+    }
+    
+    let events = ReceiverStream::new(events).map(|event| {
+        Event::default()
+            .id(event.id.to_string())
+            .event(event.event_type.clone())
+            .json_data(&event)
+    });
+    
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()).into_response())
+}
+
+async fn forward_to_socket(mut socket: WebSocket, mut events: mpsc::Receiver<StreamEvent>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+                let Ok(text) = serde_json::to_string(&event) else { continue };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Stream a whole table in block order
+#[utoipa::path(
+    get,
+    path = "/export/{table}",
+    tag = "export",
+    params(
+        ("table" = ExportTable, Path, description = "Table to export"),
+        ExportQuery,
+    ),
+    responses(
+        (status = 200, description = "CSV with a header row, or one JSON object per line", content(
+            (String = "text/csv"),
+            (String = "application/x-ndjson"),
+        )),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn export_table(
+    State(state): State<Arc<AppState>>,
+    Path(table): Path<ExportTable>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    if let (Some(from), Some(to)) = (query.from_block, query.to_block) {
+        if from > to {
+            return Err(ApiError::BadRequest("from_block must not exceed to_block".to_string()));
+        }
+    }
+    
+    let db = postgres_reads(&state)?;
+    let range = BlockRange { from_block: query.from_block, to_block: query.to_block };
+    let (name, body) = match table {
+        ExportTable::DataSubmissions => (
+            "data_submissions",
+            export::stream_body::<DataSubmission>(db.clone(), query.format, range),
+        ),
+        ExportTable::DeviceEvents => (
+            "device_events",
+            export::stream_body::<DeviceEvent>(db.clone(), query.format, range),
+        ),
+    };
+    
+    let disposition = format!("attachment; filename=\"{}.{}\"", name, query.format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/submissions",
+    tag = "analytics",
+    params(SubmissionAnalyticsQuery),
+    responses(
+        (status = 200, body = SubmissionSeries),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn get_submission_analytics(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubmissionAnalyticsQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<SubmissionSeries>, ApiError> {
+    as_of.current_only()?;
+    let bucket = query.bucket;
+    let to = query.to.unwrap_or_else(|| Utc::now().timestamp());
+    let from = query.from.unwrap_or(to - bucket.default_span());
+    if from >= to {
+        return Err(ApiError::BadRequest("from must be before to".to_string()));
+    }
+    
+    // Widen the range to whole buckets
+    let from = bucket.floor(from);
+    let to = bucket.floor(to - 1) + bucket.width();
+    if (to - from) / bucket.width() > MAX_ANALYTICS_BUCKETS {
+        return Err(ApiError::BadRequest(format!(
+            "Range spans more than {} buckets",
+            MAX_ANALYTICS_BUCKETS
+        )));
+    }
+    
+    let buckets = analytics::submission_series(postgres_reads(&state)?, bucket, query.group_by, from, to).await?;
+    
+    Ok(Json(SubmissionSeries {
+        bucket,
+        group_by: query.group_by,
+        from,
+        to,
+        buckets,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/alerts",
+    tag = "analytics",
+    params(
+        PaginationQuery,
+        AlertQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<Alert>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn get_alerts(
+    State(state): State<Arc<AppState>>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(filter): Query<AlertQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<PaginatedResponse<Alert>>, ApiError> {
+    as_of.current_only()?;
+    let db = postgres_reads(&state)?;
+    let Paging { limit, offset } = pagination.paging()?;
+    let subject = filter.subject.as_deref().map(canonical_text);
+    
+    let total: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM alerts
+        WHERE ($1::TEXT IS NULL OR scope = $1)
+            AND ($2::TEXT IS NULL OR kind = $2)
+            AND ($3::TEXT IS NULL OR subject = $3)
+        "#
+    )
+    .bind(filter.scope)
+    .bind(filter.kind)
+    .bind(&subject)
+    .fetch_one(db)
+    .await?;
+    
+    let alerts: Vec<Alert> = sqlx::query_as::<_, Alert>(&format!(
+        r#"
+        SELECT {ALERT_COLUMNS} FROM alerts
+        WHERE ($1::TEXT IS NULL OR scope = $1)
+            AND ($2::TEXT IS NULL OR kind = $2)
+            AND ($3::TEXT IS NULL OR subject = $3)
+        ORDER BY id DESC
+        LIMIT $4 OFFSET $5
+        "#
+    ))
+    .bind(filter.scope)
+    .bind(filter.kind)
+    .bind(&subject)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(Alert::checksummed)
+    .collect();
+    
+    Ok(Json(PaginatedResponse {
+        data: alerts,
+        page: pagination.page,
+        limit: pagination.limit,
+        total,
+        archived: None,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "GraphQL request: `query`, optional `variables` and `operationName`"),
+    responses(
+        (status = 200, description = "GraphQL response", body = Object),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn graphql_handler(
+    schema: Option<Extension<IndexerSchema>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Result<Json<async_graphql::Response>, ApiError> {
+    let Some(Extension(schema)) = schema else {
+        return Err(ApiError::Unsupported("GraphQL requires the PostgreSQL backend".to_string()));
+    };
+    
+    Ok(Json(schema.execute(request).await))
+}
+
+/// In-browser GraphQL IDE
+#[utoipa::path(
+    get,
+    path = "/graphql",
+    tag = "graphql",
+    responses(
+        (status = 200, description = "GraphiQL IDE", content_type = "text/html", body = String),
+    )
+)]
+async fn graphiql() -> Html<String> {
+    Html(async_graphql::http::GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI rendering `/openapi.json`
+async fn api_docs() -> Html<&'static str> {
+    Html(include_str!("docs.html"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "Prometheus text exposition", content_type = "text/plain", body = String),
+    )
+)]
+async fn get_metrics(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    let metrics = &state.metrics;
+    
+    // Gauges derived from shared state are refreshed at scrape time
+    {
+        let status = state.status.read().await;
+        metrics.chain_head.set(status.chain_head as i64);
+        for (contract, progress) in &status.contracts {
+            metrics
+                .checkpoint_block
+                .with_label_values(&[contract])
+                .set(progress.indexed_block as i64);
+        }
+    }
+    for (pool, storage) in [("write", &state.storage), ("read", &state.reads)] {
+        let (pool_size, pool_idle) = storage.pool_usage();
+        metrics.db_pool_size.with_label_values(&[pool]).set(pool_size as i64);
+        metrics.db_pool_in_use.with_label_values(&[pool]).set(pool_size as i64 - pool_idle as i64);
+    }
+    
+    let body = metrics.render().map_err(ApiError::Internal)?;
+    
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        body,
+    ).into_response())
+}
+
+/// The latest block, capped at what the read replica has caught up to
+async fn readable_latest_block(state: &AppState) -> u64 {
     let latest_block = *state.latest_block.read().await;
+    state.status.read().await.readable_block(latest_block)
+}
+
+/// Liveness: fails only when the process can no longer recover on its own,
+/// i.e. the indexer task has exited
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "Indexer running", body = HealthResponse),
+        (status = 503, description = "Indexer stopped", body = HealthResponse),
+    )
+)]
+async fn health_check(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthResponse>) {
+    let latest_block = readable_latest_block(&state).await;
+    let running = state.status.read().await.running;
     
-    Ok(Json(HealthResponse {
-        status: "healthy".to_string(),
+    let (code, status) = if running {
+        (StatusCode::OK, "healthy")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unhealthy")
+    };
+    
+    (code, Json(HealthResponse {
+        status: status.to_string(),
         latest_block,
     }))
 }
 
-async fn get_stats(State(state): State<Arc<AppState>>) -> Result<Json<StatsResponse>, ApiError> {
-    // Simplified stats for now - will be populated as events are indexed
-    let latest_block = *state.latest_block.read().await;
+/// Readiness: the database answers, the RPC answers and no contract has
+/// fallen further behind the chain head than `max_lag_blocks`
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve", body = ReadinessResponse),
+        (status = 503, description = "A dependency check failed", body = ReadinessResponse),
+    )
+)]
+async fn readiness_check(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessResponse>) {
+    let latest_block = readable_latest_block(&state).await;
+    
+    let components = vec![
+        check_database(&state).await,
+        check_rpc(&state).await,
+        check_indexing(&state).await,
+    ];
+    
+    let ready = components.iter().all(|c| c.healthy);
+    let (code, status) = if ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+    
+    (code, Json(ReadinessResponse {
+        status: status.to_string(),
+        latest_block,
+        components,
+    }))
+}
+
+fn component(name: &str, failure: Option<String>) -> ComponentStatus {
+    ComponentStatus {
+        name: name.to_string(),
+        healthy: failure.is_none(),
+        reason: failure,
+    }
+}
+
+async fn check_database(state: &AppState) -> ComponentStatus {
+    let mut failures = Vec::new();
+    
+    for (name, storage) in [("database", &state.storage), ("read database", &state.reads)] {
+        match tokio::time::timeout(READY_CHECK_TIMEOUT, storage.ping()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => failures.push(format!("{} ping failed: {}", name, e)),
+            Err(_) => failures.push(format!("{} ping timed out", name)),
+        }
+    }
+    
+    let failure = (!failures.is_empty()).then(|| failures.join("; "));
+    component("database", failure)
+}
+
+async fn check_rpc(state: &AppState) -> ComponentStatus {
+    let Some(provider) = state.provider.read().await.clone() else {
+        return component("rpc", Some("indexer has not connected to the RPC".to_string()));
+    };
+    
+    let ping = state.metrics.observe_rpc("eth_blockNumber", provider.get_block_number());
+    
+    let failure = match tokio::time::timeout(READY_CHECK_TIMEOUT, ping).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(format!("RPC request failed: {}", e)),
+        Err(_) => Some("RPC request timed out".to_string()),
+    };
+    
+    component("rpc", failure)
+}
+
+async fn check_indexing(state: &AppState) -> ComponentStatus {
+    let status = state.status.read().await;
+    let max_lag = state.config.max_lag_blocks;
+    
+    let problems = status.indexing_problems(max_lag);
+    
+    let failure = if !status.running {
+        Some("indexer task has stopped".to_string())
+    } else if problems.is_empty() {
+        None
+    } else {
+        Some(problems.join("; "))
+    };
+    
+    component("indexing", failure)
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "health",
+    responses(
+        (status = 200, body = StatsResponse),
+        (status = 400, description = "as_of_block is not supported", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_stats(
+    State(state): State<Arc<AppState>>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<StatsResponse>, ApiError> {
+    as_of.current_only()?;
+    let latest_block = readable_latest_block(&state).await;
+    
+    let counts = state.reads.counts(Utc::now().timestamp()).await?;
+    
+    let status = state.status.read().await;
+    let contracts = status
+        .contracts
+        .iter()
+        .map(|(name, progress)| {
+            let readable = status.readable(name, progress);
+            ContractIndexingStats {
+                contract: name.to_string(),
+                indexed_block: readable.indexed_block,
+                lag_blocks: status.lag_blocks(&readable),
+                lag_seconds: status.lag_seconds(&readable),
+                replica_lag_blocks: status
+                    .replica_blocks
+                    .as_ref()
+                    .map(|_| progress.indexed_block - readable.indexed_block),
+                live: readable.live,
+            }
+        })
+        .collect();
     
     Ok(Json(StatsResponse {
-        verifier_count: 0,
-        device_count: 0,
-        data_submission_count: 0,
+        verifier_count: counts.verifiers,
+        device_count: counts.devices,
+        data_submission_count: counts.data_submissions,
         latest_block,
+        submissions_24h: counts.submissions_24h,
+        submissions_7d: counts.submissions_7d,
+        submissions_per_hour_24h: counts.submissions_24h as f64 / 24.0,
+        submissions_per_hour_7d: counts.submissions_7d as f64 / (7.0 * 24.0),
+        active_devices_24h: counts.active_devices_24h,
+        active_devices_7d: counts.active_devices_7d,
+        chain_head: status.chain_head,
+        contracts,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/verifiers",
+    tag = "verifiers",
+    params(
+        PaginationQuery,
+        VerifierQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<VerifierInfo>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
 async fn get_verifiers(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     Query(pagination): Query<PaginationQuery>,
+    Query(filter): Query<VerifierQuery>,
+    Query(as_of): Query<AsOfQuery>,
 ) -> Result<Json<PaginatedResponse<VerifierInfo>>, ApiError> {
-    // Simplified response for now
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .verifiers(as_of_block, filter.active, pagination.paging()?)
+        .await?;
+    
     Ok(Json(PaginatedResponse {
-        data: vec![],
+        data: page.rows,
         page: pagination.page,
         limit: pagination.limit,
-        total: 0,
+        total: page.total,
+        archived: None,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/verifiers/{address}/events",
+    tag = "verifiers",
+    params(
+        ("address" = String, Path, description = "Verifier address"),
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<VerifierEvent>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
 async fn get_verifier_events(
     State(state): State<Arc<AppState>>,
-    Path(address): Path<String>,
+    Path(address): Path<HexAddress>,
     Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
 ) -> Result<Json<PaginatedResponse<VerifierEvent>>, ApiError> {
-    let offset = ((pagination.page - 1) * pagination.limit) as i64;
-    let limit = pagination.limit as i64;
-    
-    let total = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM verifier_events WHERE verifier_address = $1",
-        address
-    )
-    .fetch_one(&state.db)
-    .await?
-    .unwrap_or(0);
-    
-    let events = sqlx::query_as!(
-        VerifierEvent,
-        r#"
-        SELECT 
-            id,
-            verifier_address,
-            event_type as "event_type: _",
-            timestamp,
-            block_number,
-            tx_hash,
-            created_at
-        FROM verifier_events
-        WHERE verifier_address = $1
-        ORDER BY timestamp DESC
-        LIMIT $2 OFFSET $3
-        "#,
-        address,
-        limit,
-        offset
-    )
-    .fetch_all(&state.db)
-    .await?;
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .verifier_events(address, as_of_block, pagination.paging()?)
+        .await?;
     
     Ok(Json(PaginatedResponse {
-        data: events,
+        data: page.rows,
         page: pagination.page,
         limit: pagination.limit,
-        total,
+        total: page.total,
+        archived: None,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/devices",
+    tag = "devices",
+    params(
+        PaginationQuery,
+        DeviceStatusQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DeviceInfo>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
 async fn get_devices(
     State(state): State<Arc<AppState>>,
     Query(pagination): Query<PaginationQuery>,
+    Query(filter): Query<DeviceStatusQuery>,
+    Query(as_of): Query<AsOfQuery>,
 ) -> Result<Json<PaginatedResponse<DeviceInfo>>, ApiError> {
-    let offset = ((pagination.page - 1) * pagination.limit) as i64;
-    let limit = pagination.limit as i64;
+    let as_of_block = as_of.block()?;
+    let cutoffs = StaleCutoffs::at(&state.config, Utc::now().timestamp());
+    let filter = DeviceFilter { status: filter.status, zone: None };
     
-    let total = sqlx::query_scalar!(
-        "SELECT COUNT(DISTINCT device_id) FROM device_events WHERE event_type = 'registered'"
-    )
-    .fetch_one(&state.db)
-    .await?
-    .unwrap_or(0);
-    
-    let devices = sqlx::query_as!(
-        DeviceInfo,
-        r#"
-        SELECT DISTINCT 
-            device_id,
-            FIRST_VALUE(owner_address) OVER (PARTITION BY device_id ORDER BY timestamp DESC) as owner_address,
-            MIN(timestamp) as registered_at,
-            MAX(device_type) as device_type,
-            MAX(zone) as zone
-        FROM device_events
-        WHERE event_type = 'registered'
-        GROUP BY device_id, owner_address, timestamp
-        ORDER BY MIN(timestamp) DESC
-        LIMIT $1 OFFSET $2
-        "#,
-        limit,
-        offset
-    )
-    .fetch_all(&state.db)
-    .await?;
+    let mut page = state
+        .reads
+        .devices(as_of_block, &filter, &cutoffs, pagination.paging()?)
+        .await?;
     
+    name_device_types(&state.config.device_type_names, &mut page.rows);
     Ok(Json(PaginatedResponse {
-        data: devices,
+        data: page.rows,
         page: pagination.page,
         limit: pagination.limit,
-        total,
+        total: page.total,
+        archived: state.reads.archived_rows("device_events", as_of_block).await?,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/devices/{id}",
+    tag = "devices",
+    params(
+        ("id" = String, Path, description = "Device ID"),
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = DeviceInfo),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 404, description = "Device not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
 async fn get_device(
     State(state): State<Arc<AppState>>,
-    Path(device_id): Path<String>,
+    Path(device_id): Path<HexBytes32>,
+    Query(as_of): Query<AsOfQuery>,
 ) -> Result<Json<DeviceInfo>, ApiError> {
-    let device = sqlx::query_as!(
-        DeviceInfo,
-        r#"
-        SELECT 
-            device_id,
-            owner_address,
-            registered_at,
-            device_type,
-            zone
-        FROM (
-            SELECT DISTINCT 
-                device_id,
-                FIRST_VALUE(owner_address) OVER (PARTITION BY device_id ORDER BY timestamp DESC) as owner_address,
-                MIN(timestamp) as registered_at,
-                MAX(device_type) as device_type,
-                MAX(zone) as zone
-            FROM device_events
-            WHERE device_id = $1 AND event_type = 'registered'
-            GROUP BY device_id, owner_address, timestamp
-        ) t
-        LIMIT 1
-        "#,
-        device_id
-    )
-    .fetch_optional(&state.db)
-    .await?
-    .ok_or(ApiError::NotFound("Device not found".to_string()))?;
+    let as_of_block = as_of.block()?;
+    let cutoffs = StaleCutoffs::at(&state.config, Utc::now().timestamp());
+    
+    let mut device = state
+        .reads
+        .device(device_id, as_of_block, &cutoffs)
+        .await?
+        .ok_or(ApiError::NotFound("Device not found".to_string()))?;
+    
+    device.device_type_name = state.config.device_type_names.name(device.device_type);
     
     Ok(Json(device))
 }
 
+/// Fill in each device's configured device type name
+fn name_device_types(names: &DeviceTypeNames, devices: &mut [DeviceInfo]) {
+    for device in devices {
+        device.device_type_name = names.name(device.device_type);
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/zones",
+    tag = "devices",
+    params(ActivityQuery),
+    responses(
+        (status = 200, body = Vec<ZoneStats>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_zones(
+    State(state): State<Arc<AppState>>,
+    Query(activity): Query<ActivityQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<Vec<ZoneStats>>, ApiError> {
+    as_of.current_only()?;
+    let zones = state.reads.zone_stats(activity.since()?).await?;
+    
+    Ok(Json(zones))
+}
+
+#[utoipa::path(
+    get,
+    path = "/zones/{zone}/devices",
+    tag = "devices",
+    params(
+        ("zone" = String, Path, description = "Zone"),
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DeviceInfo>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 404, description = "No devices in zone", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_zone_devices(
+    State(state): State<Arc<AppState>>,
+    Path(zone): Path<String>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<PaginatedResponse<DeviceInfo>>, ApiError> {
+    let as_of_block = as_of.block()?;
+    let cutoffs = StaleCutoffs::at(&state.config, Utc::now().timestamp());
+    let filter = DeviceFilter { status: None, zone: Some(&zone) };
+    
+    let mut page = state
+        .reads
+        .devices(as_of_block, &filter, &cutoffs, pagination.paging()?)
+        .await?;
+    
+    if page.total == 0 {
+        return Err(ApiError::NotFound("No devices in zone".to_string()));
+    }
+    
+    name_device_types(&state.config.device_type_names, &mut page.rows);
+    Ok(Json(PaginatedResponse {
+        data: page.rows,
+        page: pagination.page,
+        limit: pagination.limit,
+        total: page.total,
+        archived: state.reads.archived_rows("device_events", as_of_block).await?,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/device-types",
+    tag = "devices",
+    params(ActivityQuery),
+    responses(
+        (status = 200, body = Vec<DeviceTypeStats>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_device_types(
+    State(state): State<Arc<AppState>>,
+    Query(activity): Query<ActivityQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<Vec<DeviceTypeStats>>, ApiError> {
+    as_of.current_only()?;
+    let mut device_types = state.reads.device_type_stats(activity.since()?).await?;
+    for stats in &mut device_types {
+        stats.name = state.config.device_type_names.name(Some(stats.device_type));
+    }
+    
+    Ok(Json(device_types))
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/events",
+    tag = "devices",
+    params(
+        ("id" = String, Path, description = "Device ID"),
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DeviceEvent>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
 async fn get_device_events(
     State(state): State<Arc<AppState>>,
-    Path(device_id): Path<String>,
+    Path(device_id): Path<HexBytes32>,
     Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
 ) -> Result<Json<PaginatedResponse<DeviceEvent>>, ApiError> {
-    let offset = ((pagination.page - 1) * pagination.limit) as i64;
-    let limit = pagination.limit as i64;
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .device_events(device_id, as_of_block, pagination.paging()?)
+        .await?;
     
-    let total = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM device_events WHERE device_id = $1",
-        device_id
+    Ok(Json(PaginatedResponse {
+        data: page.rows,
+        page: pagination.page,
+        limit: pagination.limit,
+        total: page.total,
+        archived: state.reads.archived_rows("device_events", as_of_block).await?,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/owners",
+    tag = "devices",
+    params(
+        ("id" = String, Path, description = "Device ID"),
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DeviceOwnership>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
     )
-    .fetch_one(&state.db)
-    .await?
-    .unwrap_or(0);
+)]
+async fn get_device_owners(
+    State(state): State<Arc<AppState>>,
+    Path(device_id): Path<HexBytes32>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<PaginatedResponse<DeviceOwnership>>, ApiError> {
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .device_owners(device_id, as_of_block, pagination.paging()?)
+        .await?;
     
-    let events = sqlx::query_as!(
-        DeviceEvent,
-        r#"
-        SELECT 
-            id,
-            device_id,
-            owner_address,
-            event_type as "event_type: _",
-            device_type,
-            zone,
-            timestamp,
-            block_number,
-            tx_hash,
-            created_at
-        FROM device_events
-        WHERE device_id = $1
-        ORDER BY timestamp DESC
-        LIMIT $2 OFFSET $3
-        "#,
-        device_id,
-        limit,
-        offset
-    )
-    .fetch_all(&state.db)
-    .await?;
+    if page.total == 0 {
+        return Err(ApiError::NotFound("Device not found".to_string()));
+    }
     
     Ok(Json(PaginatedResponse {
-        data: events,
+        data: page.rows,
         page: pagination.page,
         limit: pagination.limit,
-        total,
+        total: page.total,
+        archived: None,
     }))
 }
 
-async fn get_device_data(
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/owner",
+    tag = "devices",
+    params(
+        ("id" = String, Path, description = "Device ID"),
+        OwnerAtQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = DeviceOwnership),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 404, description = "Device not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_device_owner(
     State(state): State<Arc<AppState>>,
-    Path(device_id): Path<String>,
-    Query(pagination): Query<PaginationQuery>,
-) -> Result<Json<PaginatedResponse<DataSubmission>>, ApiError> {
-    let offset = ((pagination.page - 1) * pagination.limit) as i64;
-    let limit = pagination.limit as i64;
+    Path(device_id): Path<HexBytes32>,
+    Query(query): Query<OwnerAtQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<DeviceOwnership>, ApiError> {
+    if query.block.is_some_and(|block| block < 0) {
+        return Err(ApiError::BadRequest("block must not be negative".to_string()));
+    }
     
-    // Convert device_id to hash (simplified - in production, use proper hashing)
-    let device_id_hash = hex::encode(device_id.as_bytes());
+    // `block` is the historical alias for `as_of_block` on this endpoint
+    let as_of_block = match (query.block, as_of.block()?) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
     
-    let total = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM data_submissions WHERE device_id_hash = $1",
-        device_id_hash
+    let owner = state
+        .reads
+        .device_owner(device_id, as_of_block)
+        .await?
+        .ok_or(ApiError::NotFound("Device had no owner at that block".to_string()))?;
+    
+    Ok(Json(owner))
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/transfers",
+    tag = "devices",
+    params(
+        ("id" = String, Path, description = "Device ID"),
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DeviceTransfer>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
     )
-    .fetch_one(&state.db)
-    .await?
-    .unwrap_or(0);
+)]
+async fn get_device_transfers(
+    State(state): State<Arc<AppState>>,
+    Path(device_id): Path<HexBytes32>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<PaginatedResponse<DeviceTransfer>>, ApiError> {
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .device_transfers(device_id, as_of_block, pagination.paging()?)
+        .await?;
     
-    let submissions = sqlx::query_as!(
-        DataSubmission,
-        r#"
-        SELECT 
-            id,
-            data_hash,
-            device_id_hash,
-            device_owner,
-            timestamp,
-            block_number,
-            tx_hash,
-            created_at
-        FROM data_submissions
-        WHERE device_id_hash = $1
-        ORDER BY timestamp DESC
-        LIMIT $2 OFFSET $3
-        "#,
-        device_id_hash,
-        limit,
-        offset
-    )
-    .fetch_all(&state.db)
-    .await?;
+    Ok(Json(PaginatedResponse {
+        data: page.rows,
+        page: pagination.page,
+        limit: pagination.limit,
+        total: page.total,
+        archived: None,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/data",
+    tag = "data",
+    params(
+        ("id" = String, Path, description = "Device ID"),
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DataSubmission>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_device_data(
+    State(state): State<Arc<AppState>>,
+    Path(device_id): Path<HexBytes32>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<PaginatedResponse<DataSubmission>>, ApiError> {
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .data_submissions(Some(device_id_hash(&device_id)), as_of_block, pagination.paging()?)
+        .await?;
     
     Ok(Json(PaginatedResponse {
-        data: submissions,
+        data: page.rows,
         page: pagination.page,
         limit: pagination.limit,
-        total,
+        total: page.total,
+        archived: state.reads.archived_rows("data_submissions", as_of_block).await?,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/data/recent",
+    tag = "data",
+    params(
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<DataSubmission>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
 async fn get_recent_data(
     State(state): State<Arc<AppState>>,
     Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
 ) -> Result<Json<PaginatedResponse<DataSubmission>>, ApiError> {
-    let offset = ((pagination.page - 1) * pagination.limit) as i64;
-    let limit = pagination.limit as i64;
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .data_submissions(None, as_of_block, pagination.paging()?)
+        .await?;
     
-    let total = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM data_submissions"
+    Ok(Json(PaginatedResponse {
+        data: page.rows,
+        page: pagination.page,
+        limit: pagination.limit,
+        total: page.total,
+        archived: state.reads.archived_rows("data_submissions", as_of_block).await?,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ownership-transfers",
+    tag = "governance",
+    params(
+        PaginationQuery,
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<OwnershipTransfer>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
     )
-    .fetch_one(&state.db)
-    .await?
-    .unwrap_or(0);
+)]
+async fn get_ownership_transfers(
+    State(state): State<Arc<AppState>>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<PaginatedResponse<OwnershipTransfer>>, ApiError> {
+    let as_of_block = as_of.block()?;
+    let page = state
+        .reads
+        .ownership_transfers(as_of_block, pagination.paging()?)
+        .await?;
+    
+    Ok(Json(PaginatedResponse {
+        data: page.rows,
+        page: pagination.page,
+        limit: pagination.limit,
+        total: page.total,
+        archived: None,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/marketplace/config",
+    tag = "marketplace",
+    params(
+        AsOfQuery,
+    ),
+    responses(
+        (status = 200, body = MarketplaceFeeHistory),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 404, description = "Marketplace config not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_marketplace_config(
+    State(state): State<Arc<AppState>>,
+    Query(as_of): Query<AsOfQuery>,
+) -> Result<Json<MarketplaceFeeHistory>, ApiError> {
+    let as_of_block = as_of.block()?;
+    
+    let history = state.reads.marketplace_fees(as_of_block).await?;
+    
+    let current = history
+        .first()
+        .cloned()
+        .ok_or(ApiError::NotFound("No marketplace config in effect".to_string()))?;
     
-    let submissions = sqlx::query_as!(
-        DataSubmission,
+    Ok(Json(MarketplaceFeeHistory { current, history }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, body = Webhook),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<Webhook>), ApiError> {
+    let db = postgres(&state)?;
+    let url = reqwest::Url::parse(&request.url)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid webhook URL: {}", e)))?;
+    webhooks::check_url(&url).await.map_err(ApiError::BadRequest)?;
+    
+    if let Some(unknown) = request
+        .event_types
+        .iter()
+        .find(|t| !stream::EVENT_TYPES.contains(&t.as_str()))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown event type {}, expected one of {}",
+            unknown,
+            stream::EVENT_TYPES.join(", ")
+        )));
+    }
+    
+    if request.secret.is_empty() {
+        return Err(ApiError::BadRequest("Webhook secret must not be empty".to_string()));
+    }
+    
+    let webhook = sqlx::query_as::<_, Webhook>(
         r#"
-        SELECT 
-            id,
-            data_hash,
-            device_id_hash,
-            device_owner,
-            timestamp,
-            block_number,
-            tx_hash,
-            created_at
-        FROM data_submissions
-        ORDER BY timestamp DESC
+        INSERT INTO webhooks (url, event_types, secret)
+        VALUES ($1, $2, $3)
+        RETURNING id, url, event_types, created_at
+        "#
+    )
+    .bind(url.as_str())
+    .bind(&request.event_types)
+    .bind(&request.secret)
+    .fetch_one(db)
+    .await?;
+    
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    params(
+        PaginationQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<Webhook>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn get_webhooks(
+    State(state): State<Arc<AppState>>,
+    Query(pagination): Query<PaginationQuery>,
+) -> Result<Json<PaginatedResponse<Webhook>>, ApiError> {
+    let db = postgres(&state)?;
+    let Paging { limit, offset } = pagination.paging()?;
+    
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM webhooks")
+        .fetch_one(db)
+        .await?;
+    
+    let webhooks = sqlx::query_as::<_, Webhook>(
+        r#"
+        SELECT id, url, event_types, created_at
+        FROM webhooks
+        ORDER BY id
         LIMIT $1 OFFSET $2
-        "#,
-        limit,
-        offset
+        "#
     )
-    .fetch_all(&state.db)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
     .await?;
     
     Ok(Json(PaginatedResponse {
-        data: submissions,
+        data: webhooks,
         page: pagination.page,
         limit: pagination.limit,
         total,
+        archived: None,
     }))
 }
 
-async fn get_ownership_transfers(
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook ID"),
+    ),
+    responses(
+        (status = 200, body = Webhook),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn get_webhook(
     State(state): State<Arc<AppState>>,
-    Query(pagination): Query<PaginationQuery>,
-) -> Result<Json<PaginatedResponse<OwnershipTransfer>>, ApiError> {
-    let offset = ((pagination.page - 1) * pagination.limit) as i64;
-    let limit = pagination.limit as i64;
-    
-    let total = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM ownership_transfers"
+    Path(id): Path<i64>,
+) -> Result<Json<Webhook>, ApiError> {
+    let db = postgres(&state)?;
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "SELECT id, url, event_types, created_at FROM webhooks WHERE id = $1"
     )
-    .fetch_one(&state.db)
+    .bind(id)
+    .fetch_optional(db)
     .await?
-    .unwrap_or(0);
+    .ok_or(ApiError::NotFound(format!("Webhook {} not found", id)))?;
+    
+    Ok(Json(webhook))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook ID"),
+    ),
+    responses(
+        (status = 204, description = "Webhook deleted"),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let db = postgres(&state)?;
+    let deleted = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(id)
+        .execute(db)
+        .await?
+        .rows_affected();
+    
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("Webhook {} not found", id)));
+    }
     
-    let transfers = sqlx::query_as!(
-        OwnershipTransfer,
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// SELECT list shared by the delivery log endpoints
+const WEBHOOK_DELIVERY_COLUMNS: &str = r#"
+    d.id,
+    d.webhook_id,
+    d.event_id,
+    e.event_type,
+    d.status::text AS status,
+    d.attempts,
+    d.next_attempt_at,
+    d.last_status_code,
+    d.last_error,
+    d.delivered_at,
+    d.created_at
+"#;
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook ID"),
+        PaginationQuery,
+        DeliveryQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<WebhookDelivery>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn get_webhook_deliveries(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<PaginatedResponse<WebhookDelivery>>, ApiError> {
+    let db = postgres(&state)?;
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM webhooks WHERE id = $1)")
+        .bind(id)
+        .fetch_one(db)
+        .await?;
+    if !exists {
+        return Err(ApiError::NotFound(format!("Webhook {} not found", id)));
+    }
+    
+    let Paging { limit, offset } = pagination.paging()?;
+    let status = query.status;
+    
+    let total: i64 = sqlx::query_scalar(
         r#"
-        SELECT 
-            id,
-            contract_type,
-            previous_owner,
-            new_owner,
-            block_number,
-            tx_hash,
-            created_at
-        FROM ownership_transfers
-        ORDER BY created_at DESC
-        LIMIT $1 OFFSET $2
-        "#,
-        limit,
-        offset
+        SELECT COUNT(*) FROM webhook_deliveries
+        WHERE webhook_id = $1 AND ($2::TEXT IS NULL OR status = $2)
+        "#
     )
-    .fetch_all(&state.db)
+    .bind(id)
+    .bind(status)
+    .fetch_one(db)
+    .await?;
+    
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(&format!(
+        r#"
+        SELECT {WEBHOOK_DELIVERY_COLUMNS}
+        FROM webhook_deliveries d
+        JOIN event_stream e ON e.id = d.event_id
+        WHERE d.webhook_id = $1 AND ($2::TEXT IS NULL OR d.status = $2)
+        ORDER BY d.id DESC
+        LIMIT $3 OFFSET $4
+        "#
+    ))
+    .bind(id)
+    .bind(status)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
     .await?;
     
     Ok(Json(PaginatedResponse {
-        data: transfers,
+        data: deliveries,
         page: pagination.page,
         limit: pagination.limit,
         total,
+        archived: None,
     }))
 }
+
+/// Requeue a dead-lettered delivery with a fresh set of attempts
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/deliveries/{delivery_id}/retry",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook ID"),
+        ("delivery_id" = i64, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, body = WebhookDelivery),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 404, description = "Dead-lettered delivery not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 501, description = "Requires the PostgreSQL backend", body = ErrorResponse),
+    )
+)]
+async fn retry_webhook_delivery(
+    State(state): State<Arc<AppState>>,
+    Path((id, delivery_id)): Path<(i64, i64)>,
+) -> Result<Json<WebhookDelivery>, ApiError> {
+    let db = postgres(&state)?;
+    let delivery = sqlx::query_as::<_, WebhookDelivery>(&format!(
+        r#"
+        WITH d AS (
+            UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+            WHERE id = $1 AND webhook_id = $2 AND status = 'dead'
+            RETURNING *
+        )
+        SELECT {WEBHOOK_DELIVERY_COLUMNS}
+        FROM d
+        JOIN event_stream e ON e.id = d.event_id
+        "#
+    ))
+    .bind(delivery_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(ApiError::NotFound(format!(
+        "No dead-lettered delivery {} for webhook {}",
+        delivery_id, id
+    )))?;
+    
+    Ok(Json(delivery))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/failed-events",
+    tag = "admin",
+    params(
+        PaginationQuery,
+        FailedEventQuery,
+    ),
+    responses(
+        (status = 200, body = PaginatedResponse<FailedEvent>),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn get_failed_events(
+    State(state): State<Arc<AppState>>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(query): Query<FailedEventQuery>,
+) -> Result<Json<PaginatedResponse<FailedEvent>>, ApiError> {
+    let page = state
+        .storage
+        .failed_events(query.contract.as_deref(), query.kind, pagination.paging()?)
+        .await?;
+    
+    Ok(Json(PaginatedResponse {
+        data: page.rows,
+        page: pagination.page,
+        limit: pagination.limit,
+        total: page.total,
+        archived: None,
+    }))
+}
+
+/// Index a failed event's log again, removing it once it succeeds; a log
+/// holding a value out of range for its column is refused
+#[utoipa::path(
+    post,
+    path = "/admin/failed-events/{id}/retry",
+    tag = "admin",
+    params(
+        ("id" = i64, Path, description = "Failed event ID"),
+    ),
+    responses(
+        (status = 200, body = FailedEventRetry),
+        (status = 400, description = "The log holds a value out of range for its column", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 404, description = "Failed event not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn retry_failed_event(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<FailedEventRetry>, ApiError> {
+    let failed_event = state
+        .storage
+        .failed_event(id)
+        .await?
+        .ok_or(ApiError::NotFound(format!("Failed event {} not found", id)))?;
+    if failed_event.kind == FailureKind::OutOfRange {
+        return Err(ApiError::BadRequest(format!(
+            "Failed event {} holds a value out of range for its column, so retrying cannot index it",
+            id
+        )));
+    }
+    
+    let log: Log = serde_json::from_value(failed_event.raw_log)
+        .map_err(|e| ApiError::Internal(format!("Stored log is unreadable: {}", e)))?;
+    
+    if let Err(e) = crate::handle_log(&state, &failed_event.contract, &log).await {
+        let failed_event = state.storage.record_failed_retry(id, &format!("{:#}", e)).await?;
+    
+        return Ok(Json(FailedEventRetry { indexed: false, failed_event }));
+    }
+    
+    state.storage.discard_failed_event(id).await?;
+    
+    Ok(Json(FailedEventRetry { indexed: true, failed_event: None }))
+}
+
+/// Drop a failed event without indexing it
+#[utoipa::path(
+    delete,
+    path = "/admin/failed-events/{id}",
+    tag = "admin",
+    params(
+        ("id" = i64, Path, description = "Failed event ID"),
+    ),
+    responses(
+        (status = 204, description = "Failed event discarded"),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 404, description = "Failed event not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+async fn discard_failed_event(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    if !state.storage.discard_failed_event(id).await? {
+        return Err(ApiError::NotFound(format!("Failed event {} not found", id)));
+    }
+    
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,67 @@
+//! Indexing progress saved to the database
+//!
+//! Each contract's indexed block is written to `indexing_progress` as it
+//! moves, after the rows for those blocks have committed. A restarted indexer
+//! resumes from it rather than fetching every log from `start_block` again,
+//! and a read replica that has replayed an entry has replayed every row up to
+//! its block as well.
+
+use crate::{status::CONTRACTS, AppState};
+use anyhow::Result;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// How often progress that has moved is saved
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Load the progress saved by the last run into the indexer status
+pub async fn restore(state: &AppState) -> Result<()> {
+    let saved = state.storage.saved_progress().await?;
+    let mut status = state.status.write().await;
+    
+    for (contract, block) in saved {
+        let (Some(contract), Ok(block @ 1..)) = (CONTRACTS.iter().find(|c| **c == contract), u64::try_from(block)) else {
+            continue;
+        };
+        info!("Resuming {} from block {}", contract, block);
+        status.contracts.entry(contract).or_default().indexed_block = block;
+    }
+    
+    Ok(())
+}
+
+/// Save each contract's progress whenever it moves, until the process exits
+pub async fn run(state: Arc<AppState>) {
+    let mut saved = BTreeMap::new();
+    let mut ticks = tokio::time::interval(SAVE_INTERVAL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    
+    loop {
+        ticks.tick().await;
+    
+        if let Err(e) = save_progress(&state, &mut saved).await {
+            warn!("Failed to save indexing progress: {}", e);
+        }
+    }
+}
+
+/// Write the contracts whose indexed block moved since the last call
+async fn save_progress(state: &AppState, saved: &mut BTreeMap<&'static str, u64>) -> Result<()> {
+    let changed: Vec<(&'static str, u64)> = {
+        let status = state.status.read().await;
+        status
+            .contracts
+            .iter()
+            .filter(|(contract, progress)| saved.get(*contract) != Some(&progress.indexed_block))
+            .map(|(contract, progress)| (*contract, progress.indexed_block))
+            .collect()
+    };
+    
+    for (contract, block) in changed {
+        state.storage.save_progress(contract, i64::try_from(block)?).await?;
+        saved.insert(contract, block);
+    }
+    
+    Ok(())
+}
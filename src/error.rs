@@ -5,8 +5,9 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
 
 #[derive(Error, Debug)]
 pub enum ApiError {
@@ -19,8 +20,23 @@ pub enum ApiError {
     #[error("Bad request: {0}")]
     BadRequest(String),
     
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    
     #[error("Internal error: {0}")]
     Internal(String),
+    
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+}
+
+/// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Short category, e.g. "Resource not found"
+    pub error: String,
+    /// Details about this particular failure
+    pub message: String,
 }
 
 impl IntoResponse for ApiError {
@@ -32,13 +48,15 @@ impl IntoResponse for ApiError {
             }
             ApiError::NotFound(_) => (StatusCode::NOT_FOUND, "Resource not found"),
             ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, "Bad request"),
+            ApiError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "Unauthorized"),
             ApiError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal error"),
+            ApiError::Unsupported(_) => (StatusCode::NOT_IMPLEMENTED, "Not implemented"),
         };
         
-        let body = Json(json!({
-            "error": error_message,
-            "message": self.to_string(),
-        }));
+        let body = Json(ErrorResponse {
+            error: error_message.to_string(),
+            message: self.to_string(),
+        });
         
         (status, body).into_response()
     }
@@ -0,0 +1,416 @@
+//! Bulk export of indexed tables as CSV, NDJSON or Parquet
+//!
+//! Rows are read with a streaming query and encoded a batch at a time, so
+//! memory use stays flat however large the export is.
+
+use crate::models::{DataSubmission, DeviceEvent};
+use anyhow::{Context, Result};
+use arrow_array::{
+    builder::{Int32Builder, Int64Builder, StringBuilder, TimestampMicrosecondBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, NaiveDate};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgRow, FromRow, Pool, Postgres};
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Rows encoded per chunk of an HTTP export, and per Parquet record batch
+const BATCH_ROWS: usize = 1000;
+
+/// Encoded chunks buffered ahead of a slow HTTP client
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum ExportTable {
+    DataSubmissions,
+    DeviceEvents,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+    
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Inclusive block range; open-ended where unset
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockRange {
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+}
+
+/// A table row that can be exported
+pub trait ExportRow: for<'r> FromRow<'r, PgRow> + Serialize + Send + Unpin + 'static {
+    const TABLE: &'static str;
+    
+    /// SELECT list, with enum columns cast to text
+    const COLUMNS: &'static str;
+    
+    /// Event timestamp used to partition Parquet output by day
+    fn timestamp(&self) -> i64;
+    
+    fn id(&self) -> i64;
+    
+    fn block_number(&self) -> i64;
+    
+    fn arrow_schema() -> SchemaRef;
+    
+    fn record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError>;
+}
+
+impl ExportRow for DataSubmission {
+    const TABLE: &'static str = "data_submissions";
+    const COLUMNS: &'static str =
+        "id, data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash, created_at";
+    
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+    
+    fn id(&self) -> i64 {
+        self.id
+    }
+    
+    fn block_number(&self) -> i64 {
+        self.block_number
+    }
+    
+    fn arrow_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("data_hash", DataType::Utf8, false),
+            Field::new("device_id_hash", DataType::Utf8, false),
+            Field::new("device_owner", DataType::Utf8, false),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("block_number", DataType::Int64, false),
+            Field::new("tx_hash", DataType::Utf8, false),
+            created_at_field(),
+        ]))
+    }
+    
+    fn record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let mut id = Int64Builder::new();
+        let mut data_hash = StringBuilder::new();
+        let mut device_id_hash = StringBuilder::new();
+        let mut device_owner = StringBuilder::new();
+        let mut timestamp = Int64Builder::new();
+        let mut block_number = Int64Builder::new();
+        let mut tx_hash = StringBuilder::new();
+        let mut created_at = created_at_builder();
+    
+        for row in rows {
+            id.append_value(row.id);
+            data_hash.append_value(row.data_hash.to_string());
+            device_id_hash.append_value(row.device_id_hash.to_string());
+            device_owner.append_value(row.device_owner.to_string());
+            timestamp.append_value(row.timestamp);
+            block_number.append_value(row.block_number);
+            tx_hash.append_value(row.tx_hash.to_string());
+            created_at.append_value(row.created_at.timestamp_micros());
+        }
+    
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(id.finish()),
+            Arc::new(data_hash.finish()),
+            Arc::new(device_id_hash.finish()),
+            Arc::new(device_owner.finish()),
+            Arc::new(timestamp.finish()),
+            Arc::new(block_number.finish()),
+            Arc::new(tx_hash.finish()),
+            Arc::new(created_at.finish()),
+        ];
+        RecordBatch::try_new(Self::arrow_schema(), columns)
+    }
+}
+
+impl ExportRow for DeviceEvent {
+    const TABLE: &'static str = "device_events";
+    const COLUMNS: &'static str = "id, device_id, owner_address, event_type::text AS event_type, \
+        device_type, zone, timestamp, block_number, tx_hash, created_at";
+    
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+    
+    fn id(&self) -> i64 {
+        self.id
+    }
+    
+    fn block_number(&self) -> i64 {
+        self.block_number
+    }
+    
+    fn arrow_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("device_id", DataType::Utf8, false),
+            Field::new("owner_address", DataType::Utf8, false),
+            Field::new("event_type", DataType::Utf8, false),
+            Field::new("device_type", DataType::Int32, true),
+            Field::new("zone", DataType::Utf8, true),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("block_number", DataType::Int64, false),
+            Field::new("tx_hash", DataType::Utf8, false),
+            created_at_field(),
+        ]))
+    }
+    
+    fn record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let mut id = Int64Builder::new();
+        let mut device_id = StringBuilder::new();
+        let mut owner_address = StringBuilder::new();
+        let mut event_type = StringBuilder::new();
+        let mut device_type = Int32Builder::new();
+        let mut zone = StringBuilder::new();
+        let mut timestamp = Int64Builder::new();
+        let mut block_number = Int64Builder::new();
+        let mut tx_hash = StringBuilder::new();
+        let mut created_at = created_at_builder();
+    
+        for row in rows {
+            id.append_value(row.id);
+            device_id.append_value(row.device_id.to_string());
+            owner_address.append_value(row.owner_address.to_string());
+            event_type.append_value(row.event_type.as_str());
+            device_type.append_option(row.device_type);
+            zone.append_option(row.zone.as_deref());
+            timestamp.append_value(row.timestamp);
+            block_number.append_value(row.block_number);
+            tx_hash.append_value(row.tx_hash.to_string());
+            created_at.append_value(row.created_at.timestamp_micros());
+        }
+    
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(id.finish()),
+            Arc::new(device_id.finish()),
+            Arc::new(owner_address.finish()),
+            Arc::new(event_type.finish()),
+            Arc::new(device_type.finish()),
+            Arc::new(zone.finish()),
+            Arc::new(timestamp.finish()),
+            Arc::new(block_number.finish()),
+            Arc::new(tx_hash.finish()),
+            Arc::new(created_at.finish()),
+        ];
+        RecordBatch::try_new(Self::arrow_schema(), columns)
+    }
+}
+
+fn created_at_field() -> Field {
+    Field::new(
+        "created_at",
+        DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        false,
+    )
+}
+
+fn created_at_builder() -> TimestampMicrosecondBuilder {
+    TimestampMicrosecondBuilder::new().with_timezone("UTC")
+}
+
+fn select_sql<T: ExportRow>(order_by: &str) -> String {
+    format!(
+        r#"
+        SELECT {columns} FROM {table}
+        WHERE block_number >= COALESCE($1::BIGINT, 0)
+          AND block_number <= COALESCE($2::BIGINT, 9223372036854775807)
+        ORDER BY {order_by}
+        "#,
+        columns = T::COLUMNS,
+        table = T::TABLE,
+    )
+}
+
+/// Stream the rows of `T` in `range` as a response body, in block order
+pub fn stream_body<T: ExportRow>(db: Pool<Postgres>, format: ExportFormat, range: BlockRange) -> Body {
+    let (sender, receiver) = mpsc::channel::<io::Result<Bytes>>(CHUNKS_IN_FLIGHT);
+    
+    tokio::spawn(async move {
+        let sql = select_sql::<T>("block_number, id");
+        let mut rows = sqlx::query_as::<_, T>(&sql)
+            .bind(range.from_block)
+            .bind(range.to_block)
+            .fetch(&db);
+        let mut batch = Vec::with_capacity(BATCH_ROWS);
+        let mut first_chunk = true;
+    
+        loop {
+            let row = match rows.next().await {
+                Some(Ok(row)) => Some(row),
+                Some(Err(e)) => {
+                    // The status line is already sent; cutting the body
+                    // short is the only way left to signal the failure
+                    warn!("Export of {} failed: {}", T::TABLE, e);
+                    let _ = sender.send(Err(io::Error::other(e))).await;
+                    return;
+                }
+                None => None,
+            };
+            let done = row.is_none();
+            batch.extend(row);
+    
+            if batch.len() == BATCH_ROWS || (done && !batch.is_empty()) {
+                let chunk = encode(&batch, format, first_chunk);
+                first_chunk = false;
+                batch.clear();
+                if sender.send(chunk.map(Bytes::from)).await.is_err() {
+                    // Client went away
+                    return;
+                }
+            }
+    
+            if done {
+                return;
+            }
+        }
+    });
+    
+    Body::from_stream(ReceiverStream::new(receiver))
+}
+
+fn encode<T: Serialize>(rows: &[T], format: ExportFormat, with_header: bool) -> io::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(with_header)
+                .from_writer(Vec::new());
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.into_inner().map_err(|e| io::Error::other(e.to_string()))
+        }
+        ExportFormat::Ndjson => {
+            let mut buffer = Vec::new();
+            for row in rows {
+                serde_json::to_writer(&mut buffer, row)?;
+                buffer.push(b'\n');
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+/// Write the rows of `T` in `range` to
+/// `<out_dir>/<table>/date=<YYYY-MM-DD>/<table>.parquet`, one file per day
+/// of event timestamp; returns the files written
+pub async fn write_parquet<T: ExportRow>(
+    db: &Pool<Postgres>,
+    out_dir: &Path,
+    range: BlockRange,
+) -> Result<Vec<PathBuf>> {
+    let sql = select_sql::<T>("timestamp, id");
+    let mut rows = sqlx::query_as::<_, T>(&sql)
+        .bind(range.from_block)
+        .bind(range.to_block)
+        .fetch(db);
+    
+    let mut written = Vec::new();
+    let mut current: Option<(NaiveDate, ArrowWriter<File>)> = None;
+    let mut batch = Vec::with_capacity(BATCH_ROWS);
+    
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        let day = day_of(row.timestamp())?;
+    
+        // Rows arrive in timestamp order, so a new day closes the previous file
+        if current.as_ref().is_some_and(|(open_day, _)| *open_day != day) {
+            let (_, mut writer) = current.take().expect("checked above");
+            flush_batch::<T>(&mut writer, &mut batch)?;
+            writer.close()?;
+        }
+    
+        if current.is_none() {
+            let path = out_dir
+                .join(T::TABLE)
+                .join(format!("date={}", day))
+                .join(format!("{}.parquet", T::TABLE));
+            current = Some((day, open_writer::<T>(&path)?));
+            info!("Writing {}", path.display());
+            written.push(path);
+        }
+    
+        batch.push(row);
+        if batch.len() == BATCH_ROWS {
+            let (_, writer) = current.as_mut().expect("opened above");
+            flush_batch::<T>(writer, &mut batch)?;
+        }
+    }
+    
+    if let Some((_, mut writer)) = current {
+        flush_batch::<T>(&mut writer, &mut batch)?;
+        writer.close()?;
+    }
+    
+    Ok(written)
+}
+
+/// Write `rows` to a single Parquet file at `path` and sync it to disk
+pub fn write_parquet_file<T: ExportRow>(path: &Path, rows: &[T]) -> Result<()> {
+    let mut writer = open_writer::<T>(path)?;
+    for chunk in rows.chunks(BATCH_ROWS) {
+        writer.write(&T::record_batch(chunk)?)?;
+    }
+    writer
+        .into_inner()?
+        .sync_all()
+        .with_context(|| format!("Failed to sync {}", path.display()))
+}
+
+fn day_of(timestamp: i64) -> Result<NaiveDate> {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.date_naive())
+        .with_context(|| format!("Event timestamp {} is out of range", timestamp))
+}
+
+fn open_writer<T: ExportRow>(path: &Path) -> Result<ArrowWriter<File>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    
+    Ok(ArrowWriter::try_new(file, T::arrow_schema(), Some(properties))?)
+}
+
+fn flush_batch<T: ExportRow>(writer: &mut ArrowWriter<File>, batch: &mut Vec<T>) -> Result<()> {
+    if !batch.is_empty() {
+        writer.write(&T::record_batch(batch)?)?;
+        batch.clear();
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,470 @@
+//! GraphQL schema over the indexed data
+//!
+//! Lists are Relay-style connections paged with opaque `after` cursors.
+//! Query cost is bounded by depth and complexity limits, with each
+//! connection costing its page size times the cost of its nodes.
+
+use crate::{
+    api::device_id_hash,
+    config::DeviceTypeNames,
+    models::*,
+    storage::sql::{DEVICE_CUSTODY_CTE, DEVICE_STATE_CTE, MARKETPLACE_FEES_CTE, VERIFIER_STATE_CTE},
+};
+use async_graphql::{
+    connection::{Connection, CursorType, Edge, OpaqueCursor},
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Error, InputObject, Object,
+    OutputType, Result, Schema,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{Pool, Postgres};
+
+pub type IndexerSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const DEFAULT_PAGE_SIZE: i32 = 20;
+const MAX_PAGE_SIZE: i32 = 100;
+
+/// Deepest selection nesting accepted
+const MAX_DEPTH: usize = 10;
+
+/// Highest total cost accepted, where each connection costs its page size
+/// times the cost of its nodes
+const MAX_COMPLEXITY: usize = 10_000;
+
+pub fn schema(db: Pool<Postgres>, device_type_names: DeviceTypeNames) -> IndexerSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(db)
+        .data(device_type_names)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+#[derive(Debug, InputObject)]
+pub struct DeviceFilter {
+    pub owner: Option<HexAddress>,
+    pub zone: Option<String>,
+    pub device_type: Option<i32>,
+}
+
+#[derive(Debug, InputObject)]
+pub struct DataSubmissionFilter {
+    /// Registry device ID, matched through its device ID hash
+    pub device_id: Option<HexBytes32>,
+    pub device_id_hash: Option<HexBytes32>,
+    pub owner: Option<HexAddress>,
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+}
+
+#[derive(Debug, InputObject)]
+pub struct DeviceTransferFilter {
+    pub device_id: Option<HexBytes32>,
+    /// Matches either side of the transfer
+    pub owner: Option<HexAddress>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn device(&self, ctx: &Context<'_>, device_id: HexBytes32) -> Result<Option<DeviceInfo>> {
+        Ok(sqlx::query_as::<_, DeviceInfo>(&format!(
+            "{DEVICE_STATE_CTE} SELECT * FROM devices WHERE device_id = $2"
+        ))
+        .bind(None::<i64>)
+        .bind(device_id)
+        .fetch_optional(db(ctx)?)
+        .await?)
+    }
+    
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn devices(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<DeviceFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<HexBytes32>, DeviceInfo>> {
+        let limit = page_size(first)?;
+        let after = decode_cursor::<HexBytes32>(after)?;
+        let filter = filter.unwrap_or(DeviceFilter { owner: None, zone: None, device_type: None });
+    
+        let devices = sqlx::query_as::<_, DeviceInfo>(&format!(
+            r#"
+            {DEVICE_STATE_CTE}
+            SELECT * FROM devices
+            WHERE ($2::BYTEA IS NULL OR device_id > $2)
+              AND ($3::BYTEA IS NULL OR owner_address = $3)
+              AND ($4::TEXT IS NULL OR zone = $4)
+              AND ($5::INTEGER IS NULL OR device_type = $5)
+            ORDER BY device_id
+            LIMIT $6
+            "#
+        ))
+        .bind(None::<i64>)
+        .bind(after)
+        .bind(filter.owner)
+        .bind(filter.zone)
+        .bind(filter.device_type)
+        .bind(limit + 1)
+        .fetch_all(db(ctx)?)
+        .await?;
+    
+        Ok(connection(devices, limit, after.is_some(), |d| d.device_id))
+    }
+    
+    async fn verifier(&self, ctx: &Context<'_>, address: HexAddress) -> Result<Option<VerifierInfo>> {
+        Ok(sqlx::query_as::<_, VerifierInfo>(&format!(
+            "{VERIFIER_STATE_CTE} SELECT address, registered_at, removed_at FROM verifiers WHERE address = $2"
+        ))
+        .bind(None::<i64>)
+        .bind(address)
+        .fetch_optional(db(ctx)?)
+        .await?)
+    }
+    
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn verifiers(
+        &self,
+        ctx: &Context<'_>,
+        active: Option<bool>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<HexAddress>, VerifierInfo>> {
+        let limit = page_size(first)?;
+        let after = decode_cursor::<HexAddress>(after)?;
+    
+        let verifiers = sqlx::query_as::<_, VerifierInfo>(&format!(
+            r#"
+            {VERIFIER_STATE_CTE}
+            SELECT address, registered_at, removed_at FROM verifiers
+            WHERE ($2::BYTEA IS NULL OR address > $2)
+              AND ($3::BOOLEAN IS NULL OR (removed_at IS NULL) = $3)
+            ORDER BY address
+            LIMIT $4
+            "#
+        ))
+        .bind(None::<i64>)
+        .bind(after)
+        .bind(active)
+        .bind(limit + 1)
+        .fetch_all(db(ctx)?)
+        .await?;
+    
+        Ok(connection(verifiers, limit, after.is_some(), |v| v.address))
+    }
+    
+    /// Data submissions, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn data_submissions(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<DataSubmissionFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, DataSubmission>> {
+        let (device_id_hash, owner, from_block, to_block) = match filter {
+            Some(filter) => {
+                let hash = filter.device_id.map(|id| device_id_hash(&id)).or(filter.device_id_hash);
+                (hash, filter.owner, filter.from_block, filter.to_block)
+            }
+            None => (None, None, None, None),
+        };
+    
+        submissions(db(ctx)?, device_id_hash, owner, from_block, to_block, first, after).await
+    }
+    
+    /// Device transfers, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn device_transfers(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<DeviceTransferFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, DeviceTransfer>> {
+        let (device_id, owner) = match filter {
+            Some(filter) => (filter.device_id, filter.owner),
+            None => (None, None),
+        };
+    
+        transfers(db(ctx)?, device_id, owner, first, after).await
+    }
+    
+    /// Contract ownership transfers, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn ownership_transfers(
+        &self,
+        ctx: &Context<'_>,
+        contract_type: Option<String>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, OwnershipTransfer>> {
+        let limit = page_size(first)?;
+        let after = decode_cursor::<i64>(after)?;
+    
+        let transfers = sqlx::query_as::<_, OwnershipTransfer>(
+            r#"
+            SELECT id, contract_type, previous_owner, new_owner, block_number, tx_hash, created_at
+            FROM ownership_transfers
+            WHERE ($1::BIGINT IS NULL OR id < $1)
+              AND ($2::TEXT IS NULL OR contract_type = $2)
+            ORDER BY id DESC
+            LIMIT $3
+            "#
+        )
+        .bind(after)
+        .bind(contract_type)
+        .bind(limit + 1)
+        .fetch_all(db(ctx)?)
+        .await?;
+    
+        Ok(connection(transfers, limit, after.is_some(), |t| t.id))
+    }
+    
+    /// The marketplace config currently in effect
+    async fn marketplace_config(&self, ctx: &Context<'_>) -> Result<Option<MarketplaceConfig>> {
+        Ok(sqlx::query_as::<_, MarketplaceConfig>(&format!(
+            "{MARKETPLACE_FEES_CTE} SELECT * FROM fees ORDER BY block_number DESC, id DESC LIMIT 1"
+        ))
+        .bind(None::<i64>)
+        .fetch_optional(db(ctx)?)
+        .await?)
+    }
+    
+    /// Every marketplace config update, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn marketplace_config_history(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, MarketplaceConfig>> {
+        let limit = page_size(first)?;
+        let after = decode_cursor::<i64>(after)?;
+    
+        let configs = sqlx::query_as::<_, MarketplaceConfig>(&format!(
+            r#"
+            {MARKETPLACE_FEES_CTE}
+            SELECT * FROM fees
+            WHERE $2::BIGINT IS NULL OR id < $2
+            ORDER BY id DESC
+            LIMIT $3
+            "#
+        ))
+        .bind(None::<i64>)
+        .bind(after)
+        .bind(limit + 1)
+        .fetch_all(db(ctx)?)
+        .await?;
+    
+        Ok(connection(configs, limit, after.is_some(), |c| c.id))
+    }
+}
+
+#[ComplexObject]
+impl DeviceInfo {
+    /// Configured name for `deviceType`
+    async fn device_type_name(&self, ctx: &Context<'_>) -> Result<Option<String>> {
+        Ok(ctx.data::<DeviceTypeNames>()?.name(self.device_type))
+    }
+    
+    /// Every holder of the device, from the registering owner on
+    #[graphql(complexity = "MAX_PAGE_SIZE as usize * child_complexity")]
+    async fn owner_history(&self, ctx: &Context<'_>) -> Result<Vec<DeviceOwnership>> {
+        Ok(sqlx::query_as::<_, DeviceOwnership>(&format!(
+            r#"
+            {DEVICE_CUSTODY_CTE}
+            SELECT
+                device_id, owner_address, acquired_block, acquired_tx_hash, acquired_at,
+                released_block, released_tx_hash, released_at
+            FROM custody
+            ORDER BY position
+            "#
+        ))
+        .bind(self.device_id)
+        .bind(None::<i64>)
+        .fetch_all(db(ctx)?)
+        .await?)
+    }
+    
+    /// Transfers of this device, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn transfers(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, DeviceTransfer>> {
+        transfers(db(ctx)?, Some(self.device_id), None, first, after).await
+    }
+    
+    /// Data submitted by this device, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn submissions(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, DataSubmission>> {
+        submissions(db(ctx)?, Some(device_id_hash(&self.device_id)), None, None, None, first, after).await
+    }
+}
+
+#[ComplexObject]
+impl DeviceOwnership {
+    /// Data the device submitted while held by this owner, newest first
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn submissions(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<i64>, DataSubmission>> {
+        // The next holder takes over from the block it acquired the device in
+        let to_block = self.released_block.map(|block| block - 1);
+    
+        submissions(
+            db(ctx)?,
+            Some(device_id_hash(&self.device_id)),
+            None,
+            Some(self.acquired_block),
+            to_block,
+            first,
+            after,
+        )
+        .await
+    }
+}
+
+#[ComplexObject]
+impl DeviceTransfer {
+    async fn device(&self, ctx: &Context<'_>) -> Result<Option<DeviceInfo>> {
+        QueryRoot.device(ctx, self.device_id).await
+    }
+}
+
+#[ComplexObject]
+impl VerifierInfo {
+    async fn active(&self) -> bool {
+        self.removed_at.is_none()
+    }
+}
+
+async fn submissions(
+    db: &Pool<Postgres>,
+    device_id_hash: Option<HexBytes32>,
+    owner: Option<HexAddress>,
+    from_block: Option<i64>,
+    to_block: Option<i64>,
+    first: Option<i32>,
+    after: Option<String>,
+) -> Result<Connection<OpaqueCursor<i64>, DataSubmission>> {
+    let limit = page_size(first)?;
+    let after = decode_cursor::<i64>(after)?;
+    
+    let submissions = sqlx::query_as::<_, DataSubmission>(
+        r#"
+        SELECT id, data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash, created_at
+        FROM data_submissions
+        WHERE ($1::BIGINT IS NULL OR id < $1)
+          AND ($2::BYTEA IS NULL OR device_id_hash = $2)
+          AND ($3::BYTEA IS NULL OR device_owner = $3)
+          AND block_number >= COALESCE($4::BIGINT, 0)
+          AND block_number <= COALESCE($5::BIGINT, 9223372036854775807)
+        ORDER BY id DESC
+        LIMIT $6
+        "#
+    )
+    .bind(after)
+    .bind(device_id_hash)
+    .bind(owner)
+    .bind(from_block)
+    .bind(to_block)
+    .bind(limit + 1)
+    .fetch_all(db)
+    .await?;
+    
+    Ok(connection(submissions, limit, after.is_some(), |s| s.id))
+}
+
+async fn transfers(
+    db: &Pool<Postgres>,
+    device_id: Option<HexBytes32>,
+    owner: Option<HexAddress>,
+    first: Option<i32>,
+    after: Option<String>,
+) -> Result<Connection<OpaqueCursor<i64>, DeviceTransfer>> {
+    let limit = page_size(first)?;
+    let after = decode_cursor::<i64>(after)?;
+    
+    let transfers = sqlx::query_as::<_, DeviceTransfer>(
+        r#"
+        SELECT id, device_id, old_owner, new_owner, timestamp, block_number, tx_hash, created_at
+        FROM device_transfers
+        WHERE ($1::BIGINT IS NULL OR id < $1)
+          AND ($2::BYTEA IS NULL OR device_id = $2)
+          AND ($3::BYTEA IS NULL OR old_owner = $3 OR new_owner = $3)
+        ORDER BY id DESC
+        LIMIT $4
+        "#
+    )
+    .bind(after)
+    .bind(device_id)
+    .bind(owner)
+    .bind(limit + 1)
+    .fetch_all(db)
+    .await?;
+    
+    Ok(connection(transfers, limit, after.is_some(), |t| t.id))
+}
+
+fn db<'a>(ctx: &Context<'a>) -> Result<&'a Pool<Postgres>> {
+    ctx.data::<Pool<Postgres>>()
+}
+
+fn page_size(first: Option<i32>) -> Result<i64> {
+    match first.unwrap_or(DEFAULT_PAGE_SIZE) {
+        size @ 1..=MAX_PAGE_SIZE => Ok(size as i64),
+        _ => Err(Error::new(format!("first must be between 1 and {}", MAX_PAGE_SIZE))),
+    }
+}
+
+fn page_complexity(first: Option<i32>, child_complexity: usize) -> usize {
+    first.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize * child_complexity
+}
+
+fn decode_cursor<K>(after: Option<String>) -> Result<Option<K>>
+where
+    K: Serialize + DeserializeOwned + Send + Sync,
+{
+    after
+        .map(|cursor| {
+            OpaqueCursor::<K>::decode_cursor(&cursor)
+                .map(|cursor| cursor.0)
+                .map_err(|_| Error::new("Invalid cursor"))
+        })
+        .transpose()
+}
+
+/// Build a connection from up to `limit + 1` rows read after the cursor;
+/// the extra row only signals that another page exists
+fn connection<K, T>(
+    mut rows: Vec<T>,
+    limit: i64,
+    has_previous: bool,
+    key: impl Fn(&T) -> K,
+) -> Connection<OpaqueCursor<K>, T>
+where
+    K: Serialize + DeserializeOwned + Send + Sync,
+    T: OutputType,
+{
+    let has_next = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    
+    let mut connection = Connection::new(has_previous, has_next);
+    connection
+        .edges
+        .extend(rows.into_iter().map(|row| Edge::new(OpaqueCursor(key(&row)), row)));
+    connection
+}
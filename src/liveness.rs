@@ -0,0 +1,135 @@
+//! Device liveness
+//!
+//! A trigger records each device's latest data submission in
+//! `device_liveness`; a device is stale once that is older than the
+//! reporting interval configured for its device type.
+
+use crate::config::Config;
+
+/// Appended to `DEVICE_STATE_CTE`: each device with `last_submission_at` and
+/// `status`, where `$2` and `$3` pair device types with the oldest submission
+/// time still counted as active, and `$4` is that time for any other type
+pub(crate) const DEVICE_LIVENESS_CTE: &str = r#"
+    , liveness AS (
+        SELECT
+            d.*,
+            l.last_submission_at,
+            CASE
+                WHEN l.last_submission_at IS NULL THEN 'never_reported'
+                WHEN l.last_submission_at >= COALESCE(c.cutoff, $4) THEN 'active'
+                ELSE 'stale'
+            END AS status
+        FROM devices d
+        LEFT JOIN device_id_hashes h ON h.device_id = d.device_id
+        LEFT JOIN device_liveness l ON l.device_id_hash = h.device_id_hash
+        LEFT JOIN UNNEST($2::INT[], $3::BIGINT[]) AS c(device_type, cutoff)
+            ON c.device_type = d.device_type
+    )
+"#;
+
+/// Oldest submission time that still counts as active, per device type
+pub struct StaleCutoffs {
+    pub device_types: Vec<i32>,
+    pub cutoffs: Vec<i64>,
+    pub default: i64,
+}
+
+impl StaleCutoffs {
+    /// Cutoffs for the configured reporting intervals as of `now`
+    pub fn at(config: &Config, now: i64) -> Self {
+        let (device_types, cutoffs) = config
+            .reporting_interval_secs
+            .iter()
+            .map(|(device_type, secs)| (device_type, now.saturating_sub_unsigned(*secs)))
+            .unzip();
+    
+        Self {
+            device_types,
+            cutoffs,
+            default: now.saturating_sub_unsigned(config.default_reporting_interval_secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn cutoffs_follow_each_device_types_interval() {
+        let config = Config {
+            reporting_interval_secs: serde_json::from_value(serde_json::json!("2=7200,1=60")).unwrap(),
+            default_reporting_interval_secs: 3600,
+            ..Config::default()
+        };
+        let cutoffs = StaleCutoffs::at(&config, 1_700_000_000);
+    
+        // Device types and their cutoffs pair up by position
+        assert_eq!(cutoffs.device_types, [1, 2]);
+        assert_eq!(cutoffs.cutoffs, [1_700_000_000 - 60, 1_700_000_000 - 7200]);
+        assert_eq!(cutoffs.default, 1_700_000_000 - 3600);
+    }
+    
+    #[test]
+    fn cutoffs_without_per_type_intervals_use_the_default() {
+        let cutoffs = StaleCutoffs::at(&Config::default(), 10_000);
+        assert!(cutoffs.device_types.is_empty() && cutoffs.cutoffs.is_empty());
+        assert_eq!(cutoffs.default, 10_000 - 3600);
+    }
+    
+    #[test]
+    fn intervals_longer_than_time_itself_saturate() {
+        let config = Config {
+            reporting_interval_secs: serde_json::from_value(serde_json::json!("1=18446744073709551615")).unwrap(),
+            default_reporting_interval_secs: u64::MAX,
+            ..Config::default()
+        };
+        let cutoffs = StaleCutoffs::at(&config, 0);
+        assert_eq!(cutoffs.cutoffs, [i64::MIN]);
+        assert_eq!(cutoffs.default, i64::MIN);
+    }    
+    #[tokio::test]
+    async fn devices_are_stale_past_their_types_cutoff() {
+        use crate::{
+            api::device_id_hash,
+            models::{DeviceStatus, HexBytes32},
+            storage::{NewDevice, NewSubmission, PoolSettings, SqliteStorage, Storage},
+            LogMeta,
+        };
+    
+        let storage = SqliteStorage::connect("sqlite::memory:", &PoolSettings::for_tests()).await.unwrap();
+        let owner = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let now = 1_700_000_000;
+        let meta = |log_index| LogMeta { block_number: 1, tx_hash: HexBytes32::from([9; 32]), log_index };
+    
+        // Type 1 reports every minute, type 2 hourly by default; types 1 and
+        // 2 last submitted 100s ago, and type 3 never has
+        for device_type in 1..=3 {
+            let device_id = HexBytes32::from([device_type as u8; 32]);
+            let device = NewDevice { device_id, owner, device_type, zone: "test", timestamp: 1 };
+            storage.register_device(&device, &meta(device_type.into())).await.unwrap();
+            if device_type < 3 {
+                let submission = NewSubmission {
+                    data_hash: HexBytes32::from([0x10 + device_type as u8; 32]),
+                    device_id_hash: device_id_hash(&device_id),
+                    owner,
+                    timestamp: now - 100,
+                };
+                storage.submit_data(&submission, &meta(10 + i64::from(device_type))).await.unwrap();
+            }
+        }
+    
+        let config = Config {
+            reporting_interval_secs: serde_json::from_value(serde_json::json!("1=60")).unwrap(),
+            default_reporting_interval_secs: 3600,
+            ..Config::default()
+        };
+        let cutoffs = StaleCutoffs::at(&config, now);
+        let expected = [(1, DeviceStatus::Stale), (2, DeviceStatus::Active), (3, DeviceStatus::NeverReported)];
+        for (device_type, status) in expected {
+            let device_id = HexBytes32::from([device_type; 32]);
+            let device = storage.device(device_id, None, &cutoffs).await.unwrap().unwrap();
+            assert_eq!(device.status, Some(status), "device type {}", device_type);
+        }
+    }
+}
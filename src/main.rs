@@ -2,25 +2,49 @@
 //! 
 //! Indexes blockchain events from VerifierRegistry, DeviceRegistry, and IoTDataPipeline contracts
 
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
 use ethers::{
     contract::{abigen, EthEvent},
-    core::types::Filter,
-    providers::{Provider, Ws, Middleware, StreamExt},
+    core::types::{Address, Filter, Log, U256},
+    providers::{Provider, ProviderError, Ws, Middleware, StreamExt},
+};
+use sqlx::{Pool, Postgres};
+use std::{
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{error, info, warn};
 
-mod api_simple;
-use api_simple as api;
+mod alerts;
+mod analytics;
+mod api;
+mod checkpoint;
 mod config;
 mod error;
+mod export;
+mod graphql;
+mod liveness;
+mod metrics;
 mod models;
+mod openapi;
+mod partitions;
+mod replica;
+mod retention;
+mod status;
+mod storage;
+mod stream;
+mod webhooks;
 
 use config::Config;
+use metrics::Metrics;
+use models::{FailureKind, HexAddress, HexBytes32};
+use status::IndexerStatus;
+use storage::{NewDevice, NewSubmission, Storage};
+use stream::EventBus;
 
 // Generate contract bindings
 abigen!(
@@ -60,15 +84,73 @@ struct Args {
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Export a table to Parquet files partitioned by day, then exit
+    Export {
+        /// Table to export
+        #[arg(value_enum)]
+        table: export::ExportTable,
+        
+        /// Directory to write `<table>/date=YYYY-MM-DD/<table>.parquet` under
+        #[arg(short, long, default_value = "export")]
+        out_dir: PathBuf,
+        
+        /// First block to include
+        #[arg(long)]
+        from_block: Option<i64>,
+        
+        /// Last block to include
+        #[arg(long)]
+        to_block: Option<i64>,
+    },
+    
+    /// Detach data_submissions partitions lying wholly below a block,
+    /// leaving them as standalone tables recorded in archives, then exit
+    DetachPartitions {
+        /// First block to keep attached
+        #[arg(long)]
+        before_block: i64,
+    },
 }
 
 /// Application state
 struct AppState {
-    db: Pool<Postgres>,
+    /// Where the indexer writes events
+    storage: Arc<dyn Storage>,
+    /// Where the API reads them, possibly a replica behind `storage`
+    reads: Arc<dyn Storage>,
+    /// The PostgreSQL pools, when that is the backend, for the features only
+    /// PostgreSQL provides
+    db: Option<Pool<Postgres>>,
+    read_db: Option<Pool<Postgres>>,
     config: Config,
     latest_block: Arc<RwLock<u64>>,
+    status: Arc<RwLock<IndexerStatus>>,
+    provider: Arc<RwLock<Option<Arc<Provider<Ws>>>>>,
+    metrics: Metrics,
+    events: EventBus,
 }
 
+/// An indexer run lasting at least this long resets the failure count
+const RECONNECT_RESET: Duration = Duration::from_secs(60);
+
+/// Wait before the first retry of a failed RPC request, doubled for each
+/// retry after it up to 64 times as long
+const RPC_RETRY_BASE: Duration = Duration::from_millis(500);
+
+/// Blocks requested per `eth_getLogs` call while catching up
+const BACKFILL_BLOCKS: u64 = 2_000;
+
+/// Blocks below the last indexed one fetched again when catching up, in case
+/// a chain reorganization replaced them; logs already stored are skipped
+const REORG_MARGIN_BLOCKS: u64 = 64;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
@@ -82,40 +164,109 @@ async fn main() -> Result<()> {
     
     info!("Starting L{{CORE}} Event Indexer");
     
-    // Load configuration
-    let config = Config::load(&args.config)?;
-    info!("Configuration loaded from: {}", args.config);
-    
-    // Connect to database
-    let db = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&config.database_url)
-        .await
-        .context("Failed to connect to database")?;
-    
-    info!("Connected to database");
-    
-    // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&db)
-        .await
-        .context("Failed to run migrations")?;
+    // Load configuration from the file, if there is one, and the environment
+    let config = Config::load(&args.config).context("Failed to load configuration")?;
+    config.validate().context("Invalid configuration")?;
+    info!("Configuration loaded successfully");
+    
+    let retention = retention::policies(&config).context("Invalid retention settings")?;
+    
+    // Connect to database and run migrations
+    let database = storage::connect(&config).await?;
+    
+    match args.command {
+        Some(Command::Export { table, out_dir, from_block, to_block }) => {
+            let db = database.postgres.as_ref().context("Export requires the PostgreSQL backend")?;
+            let range = export::BlockRange { from_block, to_block };
+            let files = match table {
+                export::ExportTable::DataSubmissions => {
+                    export::write_parquet::<models::DataSubmission>(db, &out_dir, range).await?
+                }
+                export::ExportTable::DeviceEvents => {
+                    export::write_parquet::<models::DeviceEvent>(db, &out_dir, range).await?
+                }
+            };
+            info!("Export complete: {} files written to {}", files.len(), out_dir.display());
+            return Ok(());
+        }
+        Some(Command::DetachPartitions { before_block }) => {
+            let db = database.postgres.as_ref().context("Partitions require the PostgreSQL backend")?;
+            let detached = partitions::detach_before(db, before_block).await?;
+            info!("Detached {} partitions below block {}", detached.len(), before_block);
+            return Ok(());
+        }
+        None => {}
+    }
     
-    info!("Database migrations complete");
+    if let Some(db) = &database.postgres {
+        analytics::backfill_device_hashes(db)
+            .await
+            .context("Failed to backfill device ID hashes")?;
+    }
     
     // Create application state
+    // With a replica, nothing is readable until its progress has been checked
+    let status = IndexerStatus {
+        replica_blocks: config.database_read_url.as_ref().map(|_| Default::default()),
+        ..Default::default()
+    };
+    
     let state = Arc::new(AppState {
-        db: db.clone(),
+        storage: database.storage,
+        reads: database.reads,
+        db: database.postgres,
+        read_db: database.postgres_reads,
         config: config.clone(),
         latest_block: Arc::new(RwLock::new(0)),
+        status: Arc::new(RwLock::new(status)),
+        provider: Arc::new(RwLock::new(None)),
+        metrics: Metrics::new().context("Failed to register metrics")?,
+        events: EventBus::new(),
     });
     
     // Start API server
     let api_handle = tokio::spawn(api::run_server(state.clone()));
     
-    // TODO: Re-enable event indexing after fixing SQLx macros
-    // Start event indexing
-    // let indexer_handle = tokio::spawn(run_indexer(state));
+    // Start admin API server
+    match &config.admin_token {
+        Some(token) => {
+            let admin = api::run_admin_server(state.clone(), token.clone());
+            tokio::spawn(async move {
+                if let Err(e) = admin.await {
+                    error!("Admin API server failed: {}", e);
+                }
+            });
+        }
+        None => info!("Admin API disabled: set admin_token to serve it"),
+    }
+    
+    // Start event indexing where the last run stopped
+    checkpoint::restore(&state)
+        .await
+        .context("Failed to load indexing progress")?;
+    state.status.write().await.running = true;
+    tokio::spawn(supervise_indexer(state.clone()));
+    tokio::spawn(checkpoint::run(state.clone()));
+    
+    if let (Some(read_db), Some(_)) = (&state.read_db, &config.database_read_url) {
+        // Start following the read replica's lag
+        tokio::spawn(replica::run_monitor(state.clone(), read_db.clone()));
+    }
+    
+    if let Some(db) = &state.db {
+        // Start webhook delivery
+        tokio::spawn(webhooks::run_dispatcher(state.clone(), db.clone()));
+    
+        // Start submission-rate anomaly detection
+        tokio::spawn(alerts::run_detector(state.clone(), db.clone()));
+    
+        // Start archiving rows past their retention
+        if !retention.is_empty() {
+            tokio::spawn(retention::run_archiver(state.clone(), db.clone(), retention));
+        }
+    } else if !retention.is_empty() {
+        anyhow::bail!("Retention requires the PostgreSQL backend");
+    }
     
     info!("L{{CORE}} Event Indexer started successfully");
     info!("API server running on port {}", config.api_port);
@@ -128,363 +279,698 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn _run_indexer(state: Arc<AppState>) -> Result<()> {
+/// Run the indexer, reconnecting with exponential backoff when it fails
+async fn supervise_indexer(state: Arc<AppState>) {
+    let mut failures = 0;
+    
+    loop {
+        let started = Instant::now();
+        match run_indexer(state.clone()).await {
+            Ok(()) => warn!("Event indexer stopped: subscriptions closed"),
+            Err(e) => error!("Event indexer failed: {:?}", e),
+        }
+        *state.provider.write().await = None;
+        
+        // A contract that failed left its subscription marked open
+        {
+            let mut status = state.status.write().await;
+            for contract in status::CONTRACTS {
+                status.set_live(contract, false);
+            }
+        }
+        
+        if started.elapsed() >= RECONNECT_RESET {
+            failures = 0;
+        }
+        failures += 1;
+        let max_reconnects = state.config.max_reconnects;
+        if failures > max_reconnects {
+            error!("Event indexer failed {} times in a row, giving up", failures);
+            break;
+        }
+        
+        // Doubles with each failure, up to 64s
+        let delay = Duration::from_secs(1 << (failures - 1).min(6));
+        warn!("Reconnecting to blockchain in {:?} (attempt {}/{})", delay, failures, max_reconnects);
+        tokio::time::sleep(delay).await;
+        state.metrics.ws_reconnects.inc();
+    }
+    
+    state.status.write().await.running = false;
+}
+
+/// Make an RPC request, giving each attempt `request_timeout_secs` and
+/// retrying failed attempts up to `max_retries` times
+async fn call_rpc<T, F, Fut>(state: &AppState, method: &str, mut call: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ProviderError>>,
+{
+    let timeout = Duration::from_secs(state.config.request_timeout_secs);
+    let max_retries = state.config.max_retries;
+    let mut retries = 0;
+    
+    loop {
+        let attempt = async {
+            match tokio::time::timeout(timeout, call()).await {
+                Ok(result) => result.map_err(anyhow::Error::from),
+                Err(_) => Err(anyhow!("timed out after {:?}", timeout)),
+            }
+        };
+        
+        let e = match state.metrics.observe_rpc(method, attempt).await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        if retries >= max_retries {
+            return Err(e.context(format!("{} failed after {} attempts", method, retries + 1)));
+        }
+        
+        retries += 1;
+        let delay = RPC_RETRY_BASE * (1 << (retries - 1).min(6));
+        warn!("{} failed, retrying in {:?} ({}/{}): {:#}", method, delay, retries, max_retries, e);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn run_indexer(state: Arc<AppState>) -> Result<()> {
     // Connect to blockchain
-    let provider = Provider::<Ws>::connect(&state.config.blockchain_ws_url)
+    let provider = call_rpc(&state, "connect", || Provider::<Ws>::connect(&state.config.blockchain_ws_url))
         .await
         .context("Failed to connect to blockchain")?;
     
     let provider = Arc::new(provider);
+    *state.provider.write().await = Some(provider.clone());
     
     info!("Connected to blockchain: {}", state.config.blockchain_ws_url);
     
     // Get current block
-    let current_block = provider.get_block_number().await?;
+    let current_block = call_rpc(&state, "eth_blockNumber", || provider.get_block_number()).await?;
     info!("Current block: {}", current_block);
     
     // Update latest block
     {
         let mut latest = state.latest_block.write().await;
         *latest = current_block.as_u64();
+        state.status.write().await.chain_head = current_block.as_u64();
     }
     
+    // Track the chain head for lag reporting
+    let head_handle = tokio::spawn(watch_chain_head(
+        state.clone(),
+        provider.clone(),
+    ));
+    
     // Start indexing each contract
-    let verifier_handle = tokio::spawn(index_verifier_registry(
+    let verifier_handle = tokio::spawn(index_contract(
         state.clone(),
         provider.clone(),
+        status::VERIFIER_REGISTRY,
+        state.config.verifier_registry_address.clone(),
     ));
     
-    let device_handle = tokio::spawn(index_device_registry(
+    let device_handle = tokio::spawn(index_contract(
         state.clone(),
         provider.clone(),
+        status::DEVICE_REGISTRY,
+        state.config.device_registry_address.clone(),
     ));
     
-    let pipeline_handle = tokio::spawn(index_iot_pipeline(
+    let pipeline_handle = tokio::spawn(index_contract(
         state.clone(),
         provider.clone(),
+        status::IOT_PIPELINE,
+        state.config.iot_pipeline_address.clone(),
     ));
     
     // Wait for all indexers
-    tokio::try_join!(
+    let (head, verifier, device, pipeline) = tokio::try_join!(
+        head_handle,
         verifier_handle,
         device_handle,
         pipeline_handle,
     )?;
+    head?;
+    verifier?;
+    device?;
+    pipeline?;
     
     Ok(())
 }
 
-async fn index_verifier_registry(
+async fn watch_chain_head(
     state: Arc<AppState>,
     provider: Arc<Provider<Ws>>,
 ) -> Result<()> {
-    let contract_address = state.config.verifier_registry_address.parse()?;
-    
-    info!("Indexing VerifierRegistry at: {}", contract_address);
-    
-    // Create filter for all events
-    let filter = Filter::new()
-        .address(contract_address)
-        .from_block(state.config.start_block);
-    
-    // Subscribe to events
-    let mut stream = provider.subscribe_logs(&filter).await?;
+    let mut stream = call_rpc(&state, "eth_subscribe", || provider.subscribe_blocks()).await?;
     
-    while let Some(log) = stream.next().await {
-        match log.topics[0] {
-            topic if topic == VerifierAddedFilter::signature() => {
-                let event = VerifierAddedFilter::decode_log(&log.into())?;
-                handle_verifier_added(&state.db, event).await?;
-            }
-            topic if topic == VerifierRemovedFilter::signature() => {
-                let event = VerifierRemovedFilter::decode_log(&log.into())?;
-                handle_verifier_removed(&state.db, event).await?;
-            }
-            topic if topic == OwnershipTransferredFilter::signature() => {
-                let event = OwnershipTransferredFilter::decode_log(&log.into())?;
-                handle_ownership_transferred(&state.db, event, "verifier_registry").await?;
-            }
-            _ => {
-                warn!("Unknown event topic: {:?}", log.topics[0]);
+    while let Some(block) = stream.next().await {
+        let Some(number) = block.number else {
+            continue;
+        };
+        
+        if let Ok(number) = i64::try_from(number.as_u64()) {
+            if let Err(e) = state.storage.prepare_block(number).await {
+                warn!("Failed to create partitions for block {}: {:#}", number, e);
             }
         }
         
-        // Update latest block
-        if let Some(block_number) = log.block_number {
-            let mut latest = state.latest_block.write().await;
-            *latest = block_number.as_u64();
-        }
+        let mut status = state.status.write().await;
+        status.record_head(number.as_u64(), i64::try_from(block.timestamp).ok());
     }
     
     Ok(())
 }
 
-async fn index_device_registry(
+/// Index `contract`'s logs: catch up from the last indexed block with
+/// `eth_getLogs`, then follow the live subscription. `eth_subscribe` only
+/// delivers logs from the head on, so without the catch-up anything emitted
+/// while disconnected would be lost
+async fn index_contract(
     state: Arc<AppState>,
     provider: Arc<Provider<Ws>>,
+    contract: &'static str,
+    address: String,
 ) -> Result<()> {
-    let contract_address = state.config.device_registry_address.parse()?;
+    let contract_address = address.parse::<Address>()?;
     
-    info!("Indexing DeviceRegistry at: {}", contract_address);
+    info!("Indexing {} at: {}", contract, contract_address);
     
-    // Create filter for all events
-    let filter = Filter::new()
-        .address(contract_address)
-        .from_block(state.config.start_block);
+    let filter = Filter::new().address(contract_address);
     
-    // Subscribe to events
-    let mut stream = provider.subscribe_logs(&filter).await?;
+    // Subscribe before catching up so no log falls between the two; logs
+    // delivered by both are stored once
+    let mut stream = call_rpc(&state, "eth_subscribe", || provider.subscribe_logs(&filter)).await?;
+    backfill(&state, &provider, contract, &filter).await?;
+    state.status.write().await.set_live(contract, true);
     
     while let Some(log) = stream.next().await {
-        match log.topics[0] {
-            topic if topic == DeviceRegisteredFilter::signature() => {
-                let event = DeviceRegisteredFilter::decode_log(&log.into())?;
-                handle_device_registered(&state.db, event).await?;
-            }
-            topic if topic == DeviceUpdatedFilter::signature() => {
-                let event = DeviceUpdatedFilter::decode_log(&log.into())?;
-                handle_device_updated(&state.db, event).await?;
-            }
-            topic if topic == DeviceTransferredFilter::signature() => {
-                let event = DeviceTransferredFilter::decode_log(&log.into())?;
-                handle_device_transferred(&state.db, event).await?;
-            }
-            _ => {
-                warn!("Unknown event topic: {:?}", log.topics[0]);
-            }
+        if skip_removed(contract, &log) {
+            continue;
         }
+        index_log(&state, contract, &log).await?;
+    }
+    
+    state.status.write().await.set_live(contract, false);
+    
+    Ok(())
+}
+
+/// Fetch and index `contract`'s logs from `REORG_MARGIN_BLOCKS` below the
+/// last indexed block (or from `start_block`, before anything is indexed) up
+/// to the current head, `BACKFILL_BLOCKS` at a time
+async fn backfill(
+    state: &AppState,
+    provider: &Provider<Ws>,
+    contract: &'static str,
+    filter: &Filter,
+) -> Result<()> {
+    let head = call_rpc(state, "eth_blockNumber", || provider.get_block_number()).await?.as_u64();
+    let indexed = state
+        .status
+        .read()
+        .await
+        .contracts
+        .get(contract)
+        .map_or(0, |progress| progress.indexed_block);
+    
+    let mut from = indexed.saturating_sub(REORG_MARGIN_BLOCKS).max(state.config.start_block);
+    if from <= head {
+        info!("Catching up {} from block {} to {}", contract, from, head);
+    }
+    
+    while from <= head {
+        let to = (from + BACKFILL_BLOCKS - 1).min(head);
+        let range = filter.clone().from_block(from).to_block(to);
+        let logs = call_rpc(state, "eth_getLogs", || provider.get_logs(&range)).await?;
         
-        // Update latest block
-        if let Some(block_number) = log.block_number {
-            let mut latest = state.latest_block.write().await;
-            *latest = block_number.as_u64();
+        for log in logs.iter().filter(|log| !skip_removed(contract, log)) {
+            index_log(state, contract, log).await?;
         }
+        state.status.write().await.record_log(contract, to);
+        from = to + 1;
     }
     
     Ok(())
 }
 
-async fn index_iot_pipeline(
-    state: Arc<AppState>,
-    provider: Arc<Provider<Ws>>,
-) -> Result<()> {
-    let contract_address = state.config.iot_pipeline_address.parse()?;
+/// Store one log from `contract`, recording it in `failed_events` when that
+/// fails, and advance the contract's progress to its block
+async fn index_log(state: &AppState, contract: &'static str, log: &Log) -> Result<()> {
+    let outcome = handle_log(state, contract, log).await;
+    record_failure(state.storage.as_ref(), contract, log, outcome).await?;
     
-    info!("Indexing IoTDataPipeline at: {}", contract_address);
+    // Update latest block
+    if let Some(block_number) = log.block_number.map(|b| b.as_u64()) {
+        let mut latest = state.latest_block.write().await;
+        *latest = (*latest).max(block_number);
+        state.status.write().await.record_log(contract, block_number);
+    }
     
-    // Create filter for all events
-    let filter = Filter::new()
-        .address(contract_address)
-        .from_block(state.config.start_block);
+    Ok(())
+}
+
+/// Decode and store one VerifierRegistry log
+async fn handle_verifier_registry_log(state: &AppState, log: &Log) -> Result<()> {
+    let meta = LogMeta::from_log(log)?;
+    let Some(&topic) = log.topics.first() else {
+        warn!("Log without topics in {:?}", log.transaction_hash);
+        return Ok(());
+    };
+    
+    match topic {
+        topic if topic == VerifierAddedFilter::signature() => {
+            let event = VerifierAddedFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::VERIFIER_REGISTRY,
+                "VerifierAdded",
+                handle_verifier_added(state, event, &meta),
+            ).await?;
+        }
+        topic if topic == VerifierRemovedFilter::signature() => {
+            let event = VerifierRemovedFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::VERIFIER_REGISTRY,
+                "VerifierRemoved",
+                handle_verifier_removed(state, event, &meta),
+            ).await?;
+        }
+        topic if topic == OwnershipTransferredFilter::signature() => {
+            let event = OwnershipTransferredFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::VERIFIER_REGISTRY,
+                "OwnershipTransferred",
+                handle_ownership_transferred(state, event, &meta, "verifier_registry"),
+            ).await?;
+        }
+        _ => {
+            warn!("Unknown event topic: {:?}", topic);
+        }
+    }
     
-    // Subscribe to events
-    let mut stream = provider.subscribe_logs(&filter).await?;
+    Ok(())
+}
+
+/// Decode and store one DeviceRegistry log
+async fn handle_device_registry_log(state: &AppState, log: &Log) -> Result<()> {
+    let meta = LogMeta::from_log(log)?;
+    let Some(&topic) = log.topics.first() else {
+        warn!("Log without topics in {:?}", log.transaction_hash);
+        return Ok(());
+    };
+    
+    match topic {
+        topic if topic == DeviceRegisteredFilter::signature() => {
+            let event = DeviceRegisteredFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::DEVICE_REGISTRY,
+                "DeviceRegistered",
+                handle_device_registered(state, event, &meta),
+            ).await?;
+        }
+        topic if topic == DeviceUpdatedFilter::signature() => {
+            let event = DeviceUpdatedFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::DEVICE_REGISTRY,
+                "DeviceUpdated",
+                handle_device_updated(state, event, &meta),
+            ).await?;
+        }
+        topic if topic == DeviceTransferredFilter::signature() => {
+            let event = DeviceTransferredFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::DEVICE_REGISTRY,
+                "DeviceTransferred",
+                handle_device_transferred(state, event, &meta),
+            ).await?;
+        }
+        _ => {
+            warn!("Unknown event topic: {:?}", topic);
+        }
+    }
     
-    while let Some(log) = stream.next().await {
-        match log.topics[0] {
-            topic if topic == DataSubmittedFilter::signature() => {
-                let event = DataSubmittedFilter::decode_log(&log.into())?;
-                handle_data_submitted(&state.db, event).await?;
-            }
-            topic if topic == MarketplaceConfigUpdatedFilter::signature() => {
-                let event = MarketplaceConfigUpdatedFilter::decode_log(&log.into())?;
-                handle_marketplace_config_updated(&state.db, event).await?;
-            }
-            _ => {
-                warn!("Unknown event topic: {:?}", log.topics[0]);
-            }
+    Ok(())
+}
+
+/// Decode and store one IoTDataPipeline log
+async fn handle_iot_pipeline_log(state: &AppState, log: &Log) -> Result<()> {
+    let meta = LogMeta::from_log(log)?;
+    let Some(&topic) = log.topics.first() else {
+        warn!("Log without topics in {:?}", log.transaction_hash);
+        return Ok(());
+    };
+    
+    match topic {
+        topic if topic == DataSubmittedFilter::signature() => {
+            let event = DataSubmittedFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::IOT_PIPELINE,
+                "DataSubmitted",
+                handle_data_submitted(state, event, &meta),
+            ).await?;
         }
-        
-        // Update latest block
-        if let Some(block_number) = log.block_number {
-            let mut latest = state.latest_block.write().await;
-            *latest = block_number.as_u64();
+        topic if topic == MarketplaceConfigUpdatedFilter::signature() => {
+            let event = MarketplaceConfigUpdatedFilter::decode_log(&log.clone().into())?;
+            state.metrics.observe_handler(
+                status::IOT_PIPELINE,
+                "MarketplaceConfigUpdated",
+                handle_marketplace_config_updated(state, event, &meta),
+            ).await?;
         }
+        _ => {
+            warn!("Unknown event topic: {:?}", topic);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Whether `log` is the node withdrawing a log whose block was reorganized
+/// out, rather than a new log to index
+fn skip_removed(contract: &str, log: &Log) -> bool {
+    if log.removed != Some(true) {
+        return false;
+    }
+    
+    warn!(
+        "Skipping {} log {:?} in {:?}, removed by a chain reorganization",
+        contract, log.log_index, log.transaction_hash
+    );
+    true
+}
+
+/// Block, transaction and position an indexed log was emitted at
+#[derive(Debug)]
+struct LogMeta {
+    block_number: i64,
+    tx_hash: HexBytes32,
+    log_index: i64,
+}
+
+impl LogMeta {
+    fn from_log(log: &Log) -> Result<Self, OutOfRange> {
+        let block_number = match log.block_number.map(|b| b.as_u64()) {
+            Some(b) => i64::try_from(b).map_err(|_| OutOfRange::new("block_number", b))?,
+            None => 0,
+        };
+        let log_index = match log.log_index {
+            Some(i) => checked_i64("log_index", i)?,
+            None => 0,
+        };
+    
+        Ok(Self {
+            block_number,
+            tx_hash: HexBytes32::from(log.transaction_hash.unwrap_or_default().0),
+            log_index,
+        })
+    }
+    
+    fn block_number(&self) -> i64 {
+        self.block_number
+    }
+    
+    /// Position of the log in its block; with the transaction hash it
+    /// identifies a stored row, so a log delivered twice is stored once
+    fn log_index(&self) -> i64 {
+        self.log_index
+    }
+}
+
+/// An event value too large for the column it is stored in
+#[derive(Debug, thiserror::Error)]
+#[error("{field} {value} does not fit in a BIGINT column")]
+struct OutOfRange {
+    field: &'static str,
+    value: String,
+}
+
+impl OutOfRange {
+    fn new(field: &'static str, value: impl std::fmt::Display) -> Self {
+        Self { field, value: value.to_string() }
     }
+}
+
+/// Convert a uint256 event field for a BIGINT column
+fn checked_i64(field: &'static str, value: U256) -> Result<i64, OutOfRange> {
+    i64::try_from(value).map_err(|_| OutOfRange::new(field, value))
+}
+
+/// Record a log indexing failed on in `failed_events` so indexing can carry
+/// on; only a failure to record it is passed on
+async fn record_failure(
+    storage: &dyn Storage,
+    contract: &str,
+    log: &Log,
+    outcome: Result<()>,
+) -> Result<()> {
+    let Err(e) = outcome else {
+        return Ok(());
+    };
+    
+    warn!("Failed to index {} log in {:?}: {:#}", contract, log.transaction_hash, e);
+    let kind = match e.downcast_ref::<OutOfRange>() {
+        Some(_) => FailureKind::OutOfRange,
+        None => FailureKind::Error,
+    };
+    storage.record_failed_log(contract, log, kind, &format!("{:#}", e)).await?;
     
     Ok(())
 }
 
+/// Decode and store one log from `contract`
+pub(crate) async fn handle_log(state: &AppState, contract: &str, log: &Log) -> Result<()> {
+    match contract {
+        status::VERIFIER_REGISTRY => handle_verifier_registry_log(state, log).await,
+        status::DEVICE_REGISTRY => handle_device_registry_log(state, log).await,
+        status::IOT_PIPELINE => handle_iot_pipeline_log(state, log).await,
+        _ => anyhow::bail!("Unknown contract {}", contract),
+    }
+}
+
 // Event handlers
-async fn handle_verifier_added(db: &Pool<Postgres>, event: VerifierAddedFilter) -> Result<()> {
+async fn handle_verifier_added(
+    state: &AppState,
+    event: VerifierAddedFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    let timestamp = checked_i64("timestamp", event.timestamp)?;
     info!("Verifier added: {:?}", event.verifier);
     
-    sqlx::query(
-        r#"
-        INSERT INTO verifier_events (verifier_address, event_type, timestamp, block_number, tx_hash)
-        VALUES ($1, 'added', $2, $3, $4)
-        ON CONFLICT DO NOTHING
-        "#
-    )
-    .bind(format!("{:?}", event.verifier))
-    .bind(event.timestamp.as_u64() as i64)
-    .bind(0i64) // TODO: Get from log
-    .bind("0x") // TODO: Get from log
-    .execute(db)
-    .await?;
+    let streamed = state
+        .storage
+        .add_verifier(HexAddress::from(event.verifier), timestamp, meta)
+        .await?;
+    state.events.announce(streamed);
     
     Ok(())
 }
 
-async fn handle_verifier_removed(db: &Pool<Postgres>, event: VerifierRemovedFilter) -> Result<()> {
+async fn handle_verifier_removed(
+    state: &AppState,
+    event: VerifierRemovedFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    let timestamp = checked_i64("timestamp", event.timestamp)?;
     info!("Verifier removed: {:?}", event.verifier);
     
-    sqlx::query(
-        r#"
-        INSERT INTO verifier_events (verifier_address, event_type, timestamp, block_number, tx_hash)
-        VALUES ($1, 'removed', $2, $3, $4)
-        ON CONFLICT DO NOTHING
-        "#
-    )
-    .bind(format!("{:?}", event.verifier))
-    .bind(event.timestamp.as_u64() as i64)
-    .bind(0i64) // TODO: Get from log
-    .bind("0x") // TODO: Get from log
-    .execute(db)
-    .await?;
+    state
+        .storage
+        .remove_verifier(HexAddress::from(event.verifier), timestamp, meta)
+        .await?;
     
     Ok(())
 }
 
 async fn handle_ownership_transferred(
-    db: &Pool<Postgres>,
+    state: &AppState,
     event: OwnershipTransferredFilter,
+    meta: &LogMeta,
     contract_type: &str,
 ) -> Result<()> {
     info!("Ownership transferred: {:?} -> {:?}", event.previous_owner, event.new_owner);
     
-    sqlx::query(
-        r#"
-        INSERT INTO ownership_transfers (contract_type, previous_owner, new_owner, block_number, tx_hash)
-        VALUES ($1, $2, $3, $4, $5)
-        ON CONFLICT DO NOTHING
-        "#
-    )
-    .bind(contract_type)
-    .bind(format!("{:?}", event.previous_owner))
-    .bind(format!("{:?}", event.new_owner))
-    .bind(0i64) // TODO: Get from log
-    .bind("0x") // TODO: Get from log
-    .execute(db)
-    .await?;
+    state
+        .storage
+        .transfer_ownership(
+            contract_type,
+            HexAddress::from(event.previous_owner),
+            HexAddress::from(event.new_owner),
+            meta,
+        )
+        .await?;
     
     Ok(())
 }
 
-async fn handle_device_registered(db: &Pool<Postgres>, event: DeviceRegisteredFilter) -> Result<()> {
-    info!("Device registered: {:?}", hex::encode(&event.device_id));
-    
-    sqlx::query(
-        r#"
-        INSERT INTO device_events (
-            device_id, owner_address, event_type, device_type, zone, 
-            timestamp, block_number, tx_hash
-        )
-        VALUES ($1, $2, 'registered', $3, $4, $5, $6, $7)
-        ON CONFLICT DO NOTHING
-        "#
-    )
-    .bind(hex::encode(&event.device_id))
-    .bind(format!("{:?}", event.owner))
-    .bind(event.device_type as i32)
-    .bind(event.zone)
-    .bind(event.timestamp.as_u64() as i64)
-    .bind(0i64) // TODO: Get from log
-    .bind("0x") // TODO: Get from log
-    .execute(db)
-    .await?;
+async fn handle_device_registered(
+    state: &AppState,
+    event: DeviceRegisteredFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    let timestamp = checked_i64("timestamp", event.timestamp)?;
+    info!("Device registered: {:?}", hex::encode(event.device_id));
+    
+    let device = NewDevice {
+        device_id: HexBytes32::from(event.device_id),
+        owner: HexAddress::from(event.owner),
+        device_type: i32::from(event.device_type),
+        zone: &event.zone,
+        timestamp,
+    };
+    let streamed = state.storage.register_device(&device, meta).await?;
+    state.events.announce(streamed);
     
     Ok(())
 }
 
-async fn handle_device_updated(db: &Pool<Postgres>, event: DeviceUpdatedFilter) -> Result<()> {
-    info!("Device updated: {:?}", hex::encode(&event.device_id));
-    
-    sqlx::query!(
-        r#"
-        INSERT INTO device_events (
-            device_id, owner_address, event_type, timestamp, block_number, tx_hash
+async fn handle_device_updated(
+    state: &AppState,
+    event: DeviceUpdatedFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    let timestamp = checked_i64("timestamp", event.timestamp)?;
+    info!("Device updated: {:?}", hex::encode(event.device_id));
+    
+    state
+        .storage
+        .update_device(
+            HexBytes32::from(event.device_id),
+            HexAddress::from(event.owner),
+            timestamp,
+            meta,
         )
-        VALUES ($1, $2, 'updated', $3, $4, $5)
-        ON CONFLICT DO NOTHING
-        "#,
-        hex::encode(&event.device_id),
-        format!("{:?}", event.owner),
-        event.timestamp.as_u64() as i64,
-        0i64, // TODO: Get from log
-        "0x" // TODO: Get from log
-    )
-    .execute(db)
-    .await?;
+        .await?;
     
     Ok(())
 }
 
-async fn handle_device_transferred(db: &Pool<Postgres>, event: DeviceTransferredFilter) -> Result<()> {
-    info!("Device transferred: {:?}", hex::encode(&event.device_id));
-    
-    sqlx::query!(
-        r#"
-        INSERT INTO device_transfers (
-            device_id, old_owner, new_owner, timestamp, block_number, tx_hash
+async fn handle_device_transferred(
+    state: &AppState,
+    event: DeviceTransferredFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    let timestamp = checked_i64("timestamp", event.timestamp)?;
+    info!("Device transferred: {:?}", hex::encode(event.device_id));
+    
+    state
+        .storage
+        .transfer_device(
+            HexBytes32::from(event.device_id),
+            HexAddress::from(event.old_owner),
+            HexAddress::from(event.new_owner),
+            timestamp,
+            meta,
         )
-        VALUES ($1, $2, $3, $4, $5, $6)
-        ON CONFLICT DO NOTHING
-        "#,
-        hex::encode(&event.device_id),
-        format!("{:?}", event.old_owner),
-        format!("{:?}", event.new_owner),
-        event.timestamp.as_u64() as i64,
-        0i64, // TODO: Get from log
-        "0x" // TODO: Get from log
-    )
-    .execute(db)
-    .await?;
+        .await?;
     
     Ok(())
 }
 
-async fn handle_data_submitted(db: &Pool<Postgres>, event: DataSubmittedFilter) -> Result<()> {
-    info!("Data submitted: {:?}", hex::encode(&event.data_hash));
-    
-    sqlx::query!(
-        r#"
-        INSERT INTO data_submissions (
-            data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash
-        )
-        VALUES ($1, $2, $3, $4, $5, $6)
-        ON CONFLICT DO NOTHING
-        "#,
-        hex::encode(&event.data_hash),
-        hex::encode(&event.device_id_hash),
-        format!("{:?}", event.device_owner),
-        event.timestamp.as_u64() as i64,
-        0i64, // TODO: Get from log
-        "0x" // TODO: Get from log
-    )
-    .execute(db)
-    .await?;
+async fn handle_data_submitted(
+    state: &AppState,
+    event: DataSubmittedFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    let timestamp = checked_i64("timestamp", event.timestamp)?;
+    info!("Data submitted: {:?}", hex::encode(event.data_hash));
+    
+    let submission = NewSubmission {
+        data_hash: HexBytes32::from(event.data_hash),
+        device_id_hash: HexBytes32::from(event.device_id_hash),
+        owner: HexAddress::from(event.device_owner),
+        timestamp,
+    };
+    let streamed = state.storage.submit_data(&submission, meta).await?;
+    state.events.announce(streamed);
     
     Ok(())
 }
 
 async fn handle_marketplace_config_updated(
-    db: &Pool<Postgres>,
+    state: &AppState,
     event: MarketplaceConfigUpdatedFilter,
+    meta: &LogMeta,
 ) -> Result<()> {
     info!("Marketplace config updated: base_fee={}", event.base_fee);
     
-    sqlx::query!(
-        r#"
-        INSERT INTO marketplace_config (base_fee, updated_at, block_number, tx_hash)
-        VALUES ($1, NOW(), $2, $3)
-        "#,
-        event.base_fee.as_u64() as i64,
-        0i64, // TODO: Get from log
-        "0x" // TODO: Get from log
-    )
-    .execute(db)
-    .await?;
+    // Stored as its decimal string: a uint256 fee can overflow every integer type
+    state
+        .storage
+        .update_marketplace_config(&event.base_fee.to_string(), meta)
+        .await?;
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn removed_logs_are_skipped() {
+        let mut log = Log { log_index: Some(U256::from(3)), ..Default::default() };
+        assert!(!skip_removed(status::IOT_PIPELINE, &log));
+    
+        log.removed = Some(false);
+        assert!(!skip_removed(status::IOT_PIPELINE, &log));
+    
+        log.removed = Some(true);
+        assert!(skip_removed(status::IOT_PIPELINE, &log));
+    }
+    
+    #[test]
+    fn values_up_to_i64_max_fit() {
+        let max = U256::from(i64::MAX as u64);
+        assert_eq!(checked_i64("timestamp", max).unwrap(), i64::MAX);
+    
+        let error = checked_i64("timestamp", max + 1).unwrap_err();
+        assert_eq!(error.to_string(), "timestamp 9223372036854775808 does not fit in a BIGINT column");
+    }
+    
+    #[test]
+    fn log_meta_rejects_positions_past_i64_max() {
+        let max = i64::MAX as u64;
+        let log = Log {
+            block_number: Some(max.into()),
+            log_index: Some(U256::from(max)),
+            ..Default::default()
+        };
+        let meta = LogMeta::from_log(&log).unwrap();
+        assert_eq!((meta.block_number(), meta.log_index()), (i64::MAX, i64::MAX));
+    
+        let log = Log { block_number: Some((max + 1).into()), ..Default::default() };
+        assert_eq!(LogMeta::from_log(&log).unwrap_err().field, "block_number");
+    
+        let log = Log { log_index: Some(U256::from(max) + 1), ..Default::default() };
+        assert_eq!(LogMeta::from_log(&log).unwrap_err().field, "log_index");
+    
+        // A log the node reported without a position is stored at zero
+        let meta = LogMeta::from_log(&Log::default()).unwrap();
+        assert_eq!((meta.block_number(), meta.log_index()), (0, 0));
+    }
+    
+    #[tokio::test]
+    async fn failures_are_recorded_by_kind() {
+        let storage = storage::SqliteStorage::connect("sqlite::memory:", &storage::PoolSettings::for_tests())
+            .await
+            .unwrap();
+        let log = |index: u64| Log { log_index: Some(index.into()), ..Default::default() };
+        let out_of_range = anyhow::Error::from(checked_i64("timestamp", U256::MAX).unwrap_err())
+            .context("Failed to handle DataSubmitted");
+    
+        record_failure(&storage, status::IOT_PIPELINE, &log(1), Err(out_of_range)).await.unwrap();
+        record_failure(&storage, status::IOT_PIPELINE, &log(2), Err(anyhow!("connection reset"))).await.unwrap();
+        record_failure(&storage, status::IOT_PIPELINE, &log(3), Ok(())).await.unwrap();
+    
+        let paging = storage::Paging { limit: 10, offset: 0 };
+        let kinds = |kind| storage.failed_events(None, kind, paging);
+        let all = kinds(None).await.unwrap();
+        assert_eq!(all.total, 2);
+        let out_of_range = kinds(Some(FailureKind::OutOfRange)).await.unwrap();
+        assert_eq!(out_of_range.rows.len(), 1);
+        assert_eq!(out_of_range.rows[0].log_index, "1");
+        let errors = kinds(Some(FailureKind::Error)).await.unwrap();
+        assert_eq!(errors.rows.len(), 1);
+        assert_eq!(errors.rows[0].log_index, "2");
+    }
+}
@@ -0,0 +1,177 @@
+//! Prometheus metrics for the indexer and API
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::{future::Future, time::Instant};
+
+const NAMESPACE: &str = "lcore_indexer";
+
+pub struct Metrics {
+    registry: Registry,
+    pub events_indexed: IntCounterVec,
+    pub event_errors: IntCounterVec,
+    pub handler_duration: HistogramVec,
+    pub rpc_requests: IntCounterVec,
+    pub rpc_errors: IntCounterVec,
+    pub rpc_duration: HistogramVec,
+    pub ws_reconnects: IntCounter,
+    pub checkpoint_block: IntGaugeVec,
+    pub replica_block: IntGaugeVec,
+    pub chain_head: IntGauge,
+    pub db_pool_size: IntGaugeVec,
+    pub db_pool_in_use: IntGaugeVec,
+    pub http_requests: IntCounterVec,
+    pub http_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+    
+        let events_indexed = IntCounterVec::new(
+            Opts::new("events_indexed_total", "Events persisted, by contract and event type")
+                .namespace(NAMESPACE),
+            &["contract", "event"],
+        )?;
+        let event_errors = IntCounterVec::new(
+            Opts::new("event_errors_total", "Events whose handler failed, by contract and event type")
+                .namespace(NAMESPACE),
+            &["contract", "event"],
+        )?;
+        let handler_duration = HistogramVec::new(
+            HistogramOpts::new("handler_duration_seconds", "Event handler latency")
+                .namespace(NAMESPACE),
+            &["contract", "event"],
+        )?;
+        let rpc_requests = IntCounterVec::new(
+            Opts::new("rpc_requests_total", "RPC calls made, by method").namespace(NAMESPACE),
+            &["method"],
+        )?;
+        let rpc_errors = IntCounterVec::new(
+            Opts::new("rpc_errors_total", "RPC calls that failed, by method").namespace(NAMESPACE),
+            &["method"],
+        )?;
+        let rpc_duration = HistogramVec::new(
+            HistogramOpts::new("rpc_duration_seconds", "RPC call latency").namespace(NAMESPACE),
+            &["method"],
+        )?;
+        let ws_reconnects = IntCounter::with_opts(
+            Opts::new("ws_reconnects_total", "WebSocket reconnections after the indexer failed")
+                .namespace(NAMESPACE),
+        )?;
+        let checkpoint_block = IntGaugeVec::new(
+            Opts::new("checkpoint_block", "Last block indexed, by contract").namespace(NAMESPACE),
+            &["contract"],
+        )?;
+        let replica_block = IntGaugeVec::new(
+            Opts::new("replica_block", "Last block the read replica has caught up to, by contract")
+                .namespace(NAMESPACE),
+            &["contract"],
+        )?;
+        let chain_head = IntGauge::with_opts(
+            Opts::new("chain_head_block", "Latest block announced by the node").namespace(NAMESPACE),
+        )?;
+        let db_pool_size = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Open database connections, by pool").namespace(NAMESPACE),
+            &["pool"],
+        )?;
+        let db_pool_in_use = IntGaugeVec::new(
+            Opts::new("db_pool_connections_in_use", "Database connections checked out, by pool")
+                .namespace(NAMESPACE),
+            &["pool"],
+        )?;
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests served, by route and status")
+                .namespace(NAMESPACE),
+            &["method", "route", "status"],
+        )?;
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route")
+                .namespace(NAMESPACE),
+            &["method", "route"],
+        )?;
+
+        registry.register(Box::new(events_indexed.clone()))?;
+        registry.register(Box::new(event_errors.clone()))?;
+        registry.register(Box::new(handler_duration.clone()))?;
+        registry.register(Box::new(rpc_requests.clone()))?;
+        registry.register(Box::new(rpc_errors.clone()))?;
+        registry.register(Box::new(rpc_duration.clone()))?;
+        registry.register(Box::new(ws_reconnects.clone()))?;
+        registry.register(Box::new(checkpoint_block.clone()))?;
+        registry.register(Box::new(replica_block.clone()))?;
+        registry.register(Box::new(chain_head.clone()))?;
+        registry.register(Box::new(db_pool_size.clone()))?;
+        registry.register(Box::new(db_pool_in_use.clone()))?;
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_duration.clone()))?;
+    
+        Ok(Self {
+            registry,
+            events_indexed,
+            event_errors,
+            handler_duration,
+            rpc_requests,
+            rpc_errors,
+            rpc_duration,
+            ws_reconnects,
+            checkpoint_block,
+            replica_block,
+            chain_head,
+            db_pool_size,
+            db_pool_in_use,
+            http_requests,
+            http_duration,
+        })
+    }
+    
+    /// Run an RPC call, recording its count, latency and failure
+    pub async fn observe_rpc<T, E, F>(&self, method: &str, call: F) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        self.rpc_requests.with_label_values(&[method]).inc();
+        let started = Instant::now();
+        let result = call.await;
+        self.rpc_duration
+            .with_label_values(&[method])
+            .observe(started.elapsed().as_secs_f64());
+    
+        if result.is_err() {
+            self.rpc_errors.with_label_values(&[method]).inc();
+        }
+    
+        result
+    }
+    
+    /// Run an event handler, recording its latency and outcome
+    pub async fn observe_handler<T, E, F>(&self, contract: &str, event: &str, handler: F) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let started = Instant::now();
+        let result = handler.await;
+        self.handler_duration
+            .with_label_values(&[contract, event])
+            .observe(started.elapsed().as_secs_f64());
+    
+        match result {
+            Ok(_) => self.events_indexed.with_label_values(&[contract, event]).inc(),
+            Err(_) => self.event_errors.with_label_values(&[contract, event]).inc(),
+        }
+    
+        result
+    }
+    
+    /// Render every registered metric in the Prometheus text format
+    pub fn render(&self) -> Result<String, String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+    
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
@@ -1,31 +1,253 @@
 //! Data models for the event indexer
 
+use async_graphql::SimpleObject;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use sqlx::Type;
+use ethers::{types::Address, utils::to_checksum};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, FromRow, Postgres, Sqlite, Type,
+};
+use std::{fmt, str::FromStr};
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize)]
+/// An Ethereum address, stored as BYTEA and served as hex with its EIP-55
+/// checksum. Parsing accepts hex in any case, with or without the `0x`
+/// prefix, but mixed case must carry a valid checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[schema(value_type = String)]
+pub struct HexAddress([u8; 20]);
+
+impl HexAddress {
+    pub fn checksummed(&self) -> String {
+        to_checksum(&Address::from(self.0), None)
+    }
+}
+
+impl From<Address> for HexAddress {
+    fn from(address: Address) -> Self {
+        Self(address.0)
+    }
+}
+
+impl FromStr for HexAddress {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = hex_digits(s, 20)?;
+        let address = Self(decode_hex(digits));
+    
+        let mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+            && digits.chars().any(|c| c.is_ascii_uppercase());
+        if mixed_case && address.checksummed()[2..] != *digits {
+            return Err(format!("{} does not match its EIP-55 checksum", s));
+        }
+    
+        Ok(address)
+    }
+}
+
+impl fmt::Display for HexAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.checksummed())
+    }
+}
+
+/// A 32-byte value (device ID, hash), stored as BYTEA and served as
+/// lowercase hex with a `0x` prefix. Parsing accepts any case, with or
+/// without the prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[schema(value_type = String)]
+pub struct HexBytes32([u8; 32]);
+
+impl HexBytes32 {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for HexBytes32 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl FromStr for HexBytes32 {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(decode_hex(hex_digits(s, 32)?)))
+    }
+}
+
+impl fmt::Display for HexBytes32 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+/// The hex digits of `s`, which must encode exactly `len` bytes
+fn hex_digits(s: &str, len: usize) -> Result<&str, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    if digits.len() != 2 * len || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{} is not {} bytes of hex", s, len));
+    }
+    
+    Ok(digits)
+}
+
+/// Decode digits already checked by `hex_digits`
+fn decode_hex<const N: usize>(digits: &str) -> [u8; N] {
+    let mut bytes = [0; N];
+    hex::decode_to_slice(digits, &mut bytes).expect("hex digits were validated");
+    bytes
+}
+
+/// The stored hex form of `value` if it is an address or 32 bytes of hex,
+/// for text columns that mix those with other values
+pub fn canonical_text(value: &str) -> String {
+    if let Ok(address) = value.parse::<HexAddress>() {
+        format!("0x{}", hex::encode(address.0))
+    } else if let Ok(bytes) = value.parse::<HexBytes32>() {
+        bytes.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Implements serde as a hex string and sqlx as BYTEA (BLOB on SQLite) for a
+/// fixed-size byte wrapper
+macro_rules! hex_bytes_type {
+    ($name:ident, $len:literal) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+    
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+            }
+        }
+    
+        impl Type<Postgres> for $name {
+            fn type_info() -> PgTypeInfo {
+                <Vec<u8> as Type<Postgres>>::type_info()
+            }
+    
+            fn compatible(ty: &PgTypeInfo) -> bool {
+                <Vec<u8> as Type<Postgres>>::compatible(ty)
+            }
+        }
+    
+        impl Encode<'_, Postgres> for $name {
+            fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+                <&[u8] as Encode<Postgres>>::encode(&self.0[..], buf)
+            }
+        }
+    
+        impl<'r> Decode<'r, Postgres> for $name {
+            fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+                let bytes = <&[u8] as Decode<Postgres>>::decode(value)?;
+                Ok(Self(<[u8; $len]>::try_from(bytes)?))
+            }
+        }
+    
+        impl Type<Sqlite> for $name {
+            fn type_info() -> SqliteTypeInfo {
+                <Vec<u8> as Type<Sqlite>>::type_info()
+            }
+    
+            fn compatible(ty: &SqliteTypeInfo) -> bool {
+                <Vec<u8> as Type<Sqlite>>::compatible(ty)
+            }
+        }
+    
+        impl<'q> Encode<'q, Sqlite> for $name {
+            fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+                <Vec<u8> as Encode<Sqlite>>::encode(self.0.to_vec(), buf)
+            }
+        }
+    
+        impl<'r> Decode<'r, Sqlite> for $name {
+            fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+                let bytes = <&[u8] as Decode<Sqlite>>::decode(value)?;
+                Ok(Self(<[u8; $len]>::try_from(bytes)?))
+            }
+        }
+    };
+}
+
+hex_bytes_type!(HexAddress, 20);
+hex_bytes_type!(HexBytes32, 32);
+async_graphql::scalar!(HexAddress, "Address", "An Ethereum address, returned with its EIP-55 checksum");
+async_graphql::scalar!(HexBytes32, "Bytes32", "32 bytes as 0x-prefixed hex");
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub latest_block: u64,
 }
 
-#[derive(Debug, Serialize)]
+/// Outcome of one readiness check
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ComponentStatus {
+    pub name: String,
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: String,
+    pub latest_block: u64,
+    pub components: Vec<ComponentStatus>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StatsResponse {
     pub verifier_count: i64,
     pub device_count: i64,
     pub data_submission_count: i64,
     pub latest_block: u64,
+    pub submissions_24h: i64,
+    pub submissions_7d: i64,
+    pub submissions_per_hour_24h: f64,
+    pub submissions_per_hour_7d: f64,
+    pub active_devices_24h: i64,
+    pub active_devices_7d: i64,
+    pub chain_head: u64,
+    pub contracts: Vec<ContractIndexingStats>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How far one contract's indexer trails the chain head
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ContractIndexingStats {
+    pub contract: String,
+    pub indexed_block: u64,
+    pub lag_blocks: u64,
+    pub lag_seconds: Option<i64>,
+    /// Blocks indexed but not yet on the read replica, when the API reads
+    /// from one; `indexed_block` and the lags count from the replica
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replica_lag_blocks: Option<u64>,
+    pub live: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "Verifier", complex)]
 pub struct VerifierInfo {
-    pub address: String,
+    pub address: HexAddress,
     pub registered_at: i64,
     pub removed_at: Option<i64>,
 }
 
-#[derive(Debug, Type, Serialize, Deserialize)]
+#[derive(Debug, Type, Serialize, Deserialize, ToSchema)]
 #[sqlx(type_name = "text")]
 #[sqlx(rename_all = "lowercase")]
 pub enum VerifierEventType {
@@ -33,27 +255,55 @@ pub enum VerifierEventType {
     Removed,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct VerifierEvent {
     pub id: i64,
-    pub verifier_address: String,
+    pub verifier_address: HexAddress,
     pub event_type: VerifierEventType,
     pub timestamp: i64,
     pub block_number: i64,
-    pub tx_hash: String,
+    pub tx_hash: HexBytes32,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "Device", complex)]
 pub struct DeviceInfo {
-    pub device_id: String,
-    pub owner_address: String,
+    pub device_id: HexBytes32,
+    pub owner_address: HexAddress,
     pub registered_at: i64,
     pub device_type: Option<i32>,
+    /// Configured name for `device_type`
+    #[sqlx(default)]
+    #[graphql(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_type_name: Option<String>,
     pub zone: Option<String>,
+    /// Timestamp of the device's latest data submission
+    #[sqlx(default)]
+    #[graphql(skip)]
+    pub last_submission_at: Option<i64>,
+    /// Whether the device is reporting within its expected interval
+    #[sqlx(default)]
+    #[graphql(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<DeviceStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "text")]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceStatus {
+    /// Submitted data within its expected reporting interval
+    Active,
+    /// Has not submitted data within its expected reporting interval
+    Stale,
+    /// Has never submitted data
+    NeverReported,
 }
 
-#[derive(Debug, Type, Serialize, Deserialize)]
+#[derive(Debug, Type, Serialize, Deserialize, ToSchema)]
 #[sqlx(type_name = "text")]
 #[sqlx(rename_all = "lowercase")]
 pub enum DeviceEventType {
@@ -62,60 +312,394 @@ pub enum DeviceEventType {
     Transferred,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl DeviceEventType {
+    /// The value stored in `device_events.event_type`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceEventType::Registered => "registered",
+            DeviceEventType::Updated => "updated",
+            DeviceEventType::Transferred => "transferred",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct DeviceEvent {
     pub id: i64,
-    pub device_id: String,
-    pub owner_address: String,
+    pub device_id: HexBytes32,
+    pub owner_address: HexAddress,
     pub event_type: DeviceEventType,
     pub device_type: Option<i32>,
     pub zone: Option<String>,
     pub timestamp: i64,
     pub block_number: i64,
-    pub tx_hash: String,
+    pub tx_hash: HexBytes32,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(complex)]
 pub struct DeviceTransfer {
     pub id: i64,
-    pub device_id: String,
-    pub old_owner: String,
-    pub new_owner: String,
+    pub device_id: HexBytes32,
+    pub old_owner: HexAddress,
+    pub new_owner: HexAddress,
     pub timestamp: i64,
     pub block_number: i64,
-    pub tx_hash: String,
+    pub tx_hash: HexBytes32,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// One holder in a device's chain of custody, from the block it acquired the
+/// device until the block it handed it on (open-ended for the current owner)
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(complex)]
+pub struct DeviceOwnership {
+    pub device_id: HexBytes32,
+    pub owner_address: HexAddress,
+    pub acquired_block: i64,
+    pub acquired_tx_hash: HexBytes32,
+    pub acquired_at: i64,
+    pub released_block: Option<i64>,
+    pub released_tx_hash: Option<HexBytes32>,
+    pub released_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
 pub struct DataSubmission {
     pub id: i64,
-    pub data_hash: String,
-    pub device_id_hash: String,
-    pub device_owner: String,
+    pub data_hash: HexBytes32,
+    pub device_id_hash: HexBytes32,
+    pub device_owner: HexAddress,
     pub timestamp: i64,
     pub block_number: i64,
-    pub tx_hash: String,
+    pub tx_hash: HexBytes32,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
 pub struct MarketplaceConfig {
     pub id: i64,
-    pub base_fee: i64,
+    /// Base fee in wei as a decimal string, since a uint256 can exceed what
+    /// JSON numbers represent exactly
+    pub base_fee: String,
+    /// First block the fee is in effect
     pub block_number: i64,
-    pub tx_hash: String,
+    /// Block at which a later update replaced the fee; null while it is in effect
+    pub effective_until_block: Option<i64>,
+    pub tx_hash: HexBytes32,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MarketplaceFeeHistory {
+    /// The fee in effect
+    pub current: MarketplaceConfig,
+    /// Every update, newest first, starting with `current`
+    pub history: Vec<MarketplaceConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
 pub struct OwnershipTransfer {
     pub id: i64,
     pub contract_type: String,
-    pub previous_owner: String,
-    pub new_owner: String,
+    pub previous_owner: HexAddress,
+    pub new_owner: HexAddress,
     pub block_number: i64,
-    pub tx_hash: String,
+    pub tx_hash: HexBytes32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An indexed event as delivered on `/stream/events`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct StreamEvent {
+    pub id: i64,
+    pub contract: String,
+    pub event_type: String,
+    pub owner: Option<HexAddress>,
+    /// Device ID, or device ID hash for data submissions
+    pub device: Option<HexBytes32>,
+    pub block_number: i64,
+    pub tx_hash: HexBytes32,
+    /// The stored event row
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    /// Event types delivered; empty means every type
+    pub event_types: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "text")]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Gave up after the maximum number of attempts
+    Dead,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Width of the buckets `/analytics/submissions` counts into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    #[default]
+    Hour,
+    Day,
+    /// ISO weeks, starting Monday 00:00 UTC
+    Week,
+}
+
+/// Dimension `/analytics/submissions` can split counts by
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionGrouping {
+    Zone,
+    DeviceType,
+    Owner,
+}
+
+/// Submissions in one time bucket, for one group when grouped
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct SubmissionBucket {
+    /// Unix time the bucket starts
+    pub bucket_start: i64,
+    /// Zone, device type or owner; null when ungrouped or when the device
+    /// could not be resolved
+    pub group: Option<String>,
+    pub submissions: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubmissionSeries {
+    pub bucket: TimeBucket,
+    pub group_by: Option<SubmissionGrouping>,
+    /// Start of the first bucket covered (inclusive)
+    pub from: i64,
+    /// End of the last bucket covered (exclusive)
+    pub to: i64,
+    /// Non-empty buckets in time order
+    pub buckets: Vec<SubmissionBucket>,
+}
+
+/// Devices registered in a zone and their recent submission volume
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct ZoneStats {
+    pub zone: String,
+    pub device_count: i64,
+    /// Devices that submitted data within the activity window
+    pub active_devices: i64,
+    /// Submissions within the activity window
+    pub submissions: i64,
+}
+
+/// Devices registered with a device type and their recent submission volume
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct DeviceTypeStats {
+    pub device_type: i32,
+    /// Configured name for `device_type`
+    #[sqlx(default)]
+    pub name: Option<String>,
+    pub device_count: i64,
+    /// Devices that submitted data within the activity window
+    pub active_devices: i64,
+    /// Submissions within the activity window
+    pub submissions: i64,
+}
+
+/// What an alert's rate was measured over
+#[derive(Debug, Clone, Copy, PartialEq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "text")]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlertScope {
+    Device,
+    Owner,
+    Zone,
+}
+
+impl AlertScope {
+    /// The value stored in `alerts.scope`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertScope::Device => "device",
+            AlertScope::Owner => "owner",
+            AlertScope::Zone => "zone",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "text")]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    /// Far more submissions than the baseline
+    Spike,
+    /// Far fewer submissions than the baseline
+    Drop,
+}
+
+/// A submission rate far outside its baseline
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Alert {
+    pub id: i64,
+    pub scope: AlertScope,
+    /// Device ID (or device ID hash when unmapped), owner address or zone
+    pub subject: String,
+    pub kind: AlertKind,
+    /// Unix time the anomalous hour starts
+    pub window_start: i64,
+    /// Submissions in that hour
+    pub observed: i64,
+    /// Mean submissions per hour over the baseline
+    pub baseline_mean: f64,
+    pub baseline_stddev: f64,
+    /// When the alert webhook accepted the alert
+    pub forwarded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Alert {
+    /// Give an owner subject its EIP-55 checksum, like other addresses served
+    pub fn checksummed(mut self) -> Self {
+        if self.scope == AlertScope::Owner {
+            if let Ok(owner) = self.subject.parse::<HexAddress>() {
+                self.subject = owner.checksummed();
+            }
+        }
+        self
+    }
+}
+
+/// Rows of a table moved to archive files by the retention job that a
+/// query would otherwise have included
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct ArchivedRows {
+    pub table_name: String,
+    /// Archive files the rows are in
+    pub archives: i64,
+    pub row_count: i64,
+    /// Latest block and event timestamp among the archived rows
+    pub to_block: i64,
+    pub to_timestamp: i64,
+    pub last_archived_at: DateTime<Utc>,
+}
+
+/// Why indexing a log failed
+#[derive(Debug, Clone, Copy, PartialEq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "text")]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// Indexing failed, and may succeed when retried
+    Error,
+    /// A value in the log does not fit its column, so retrying cannot index it
+    OutOfRange,
+}
+
+/// A log indexing failed on, kept for retry
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct FailedEvent {
+    pub id: i64,
+    pub contract: String,
+    /// First topic of the log, which identifies the event
+    pub topic: Option<String>,
+    /// Decimal block number as reported by the node
+    pub block_number: Option<String>,
+    /// Decimal log index, 0 when the node reported none
+    pub log_index: String,
+    /// Zero when the node reported no transaction hash
+    pub tx_hash: HexBytes32,
+    pub kind: FailureKind,
+    /// Error from the latest attempt
+    pub error: String,
+    pub attempts: i32,
+    /// The log as received from the node
+    #[schema(value_type = Object)]
+    pub raw_log: serde_json::Value,
+    pub last_attempt_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
 }
+
+/// Outcome of retrying a failed event
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FailedEventRetry {
+    /// Whether the log was indexed and removed from the failed events
+    pub indexed: bool,
+    /// The failed event as updated by an unsuccessful retry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_event: Option<FailedEvent>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    
+    #[test]
+    fn addresses_parse_in_one_case_or_checksummed() {
+        let address: HexAddress = CHECKSUMMED.parse().unwrap();
+        assert_eq!(address.to_string(), CHECKSUMMED);
+    
+        let lower = CHECKSUMMED.to_lowercase();
+        let upper = format!("0x{}", CHECKSUMMED[2..].to_uppercase());
+        for s in [lower.as_str(), upper.as_str(), &lower[2..]] {
+            assert_eq!(s.parse::<HexAddress>(), Ok(address), "{} was not accepted", s);
+        }
+    }
+    
+    #[test]
+    fn mixed_case_address_with_bad_checksum_is_rejected() {
+        // The checksum with the case of its first letter flipped
+        let bad = CHECKSUMMED.replacen('a', "A", 1);
+        assert!(bad.parse::<HexAddress>().unwrap_err().contains("EIP-55 checksum"));
+    }
+    
+    #[test]
+    fn wrong_length_is_rejected() {
+        assert!(CHECKSUMMED[..41].parse::<HexAddress>().is_err());
+        assert!(format!("{}00", CHECKSUMMED.to_lowercase()).parse::<HexAddress>().is_err());
+        assert!("0x".parse::<HexAddress>().is_err());
+        assert!(format!("0x{}", "zz".repeat(20)).parse::<HexAddress>().is_err());
+    
+        let bytes = format!("0x{}", "ab".repeat(32));
+        assert!(bytes.parse::<HexBytes32>().is_ok());
+        assert!(bytes[..65].parse::<HexBytes32>().is_err());
+        assert!(format!("{}ab", bytes).parse::<HexBytes32>().is_err());
+        // An address is too short for 32 bytes
+        assert!(CHECKSUMMED.parse::<HexBytes32>().is_err());
+    }
+    
+    #[test]
+    fn bytes32_parse_in_any_case_and_print_lowercase() {
+        let lower = format!("0x{}", "ab".repeat(32));
+        let parsed: HexBytes32 = format!("0X{}", "aB".repeat(32)).parse().unwrap();
+        assert_eq!(parsed.to_string(), lower);
+        assert_eq!(lower[2..].parse::<HexBytes32>(), Ok(parsed));
+    }
+}
@@ -0,0 +1,64 @@
+//! OpenAPI document generated from the API handlers and models
+
+use crate::{api, error::ErrorResponse, models::*};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "L{CORE} Event Indexer API",
+        description = "Query indexed L{CORE} contract events"
+    ),
+    paths(
+        api::health_check,
+        api::readiness_check,
+        api::get_stats,
+        api::get_metrics,
+        api::get_verifiers,
+        api::get_verifier_events,
+        api::get_devices,
+        api::get_device,
+        api::get_device_events,
+        api::get_device_owners,
+        api::get_device_owner,
+        api::get_device_transfers,
+        api::get_device_data,
+        api::get_zones,
+        api::get_zone_devices,
+        api::get_device_types,
+        api::get_recent_data,
+        api::get_ownership_transfers,
+        api::get_marketplace_config,
+        api::stream_events,
+        api::export_table,
+        api::get_submission_analytics,
+        api::get_alerts,
+        api::graphql_handler,
+        api::graphiql,
+        api::create_webhook,
+        api::get_webhooks,
+        api::get_webhook,
+        api::delete_webhook,
+        api::get_webhook_deliveries,
+        api::retry_webhook_delivery,
+        api::get_failed_events,
+        api::retry_failed_event,
+        api::discard_failed_event,
+    ),
+    components(schemas(ErrorResponse, StreamEvent, api::CreateWebhookRequest)),
+    tags(
+        (name = "health", description = "Liveness, readiness and indexing statistics"),
+        (name = "verifiers", description = "Verifier registry"),
+        (name = "devices", description = "Device registry and chain of custody"),
+        (name = "data", description = "Data submissions"),
+        (name = "governance", description = "Contract ownership"),
+        (name = "marketplace", description = "Marketplace configuration"),
+        (name = "stream", description = "Live event stream"),
+        (name = "export", description = "Bulk table exports"),
+        (name = "analytics", description = "Time-series aggregates and anomaly alerts"),
+        (name = "graphql", description = "GraphQL endpoint"),
+        (name = "webhooks", description = "Webhook subscriptions and delivery log, served by the admin API with `Authorization: Bearer <admin_token>`"),
+        (name = "admin", description = "Failed events awaiting retry, served by the admin API with `Authorization: Bearer <admin_token>`"),
+    )
+)]
+pub struct ApiDoc;
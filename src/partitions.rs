@@ -0,0 +1,133 @@
+//! Block-range partitions
+//!
+//! `data_submissions` and `device_events` are range-partitioned by block
+//! number. The indexer creates the partition for each block range it stores
+//! into, plus the one after it, before inserting; `data_submissions`
+//! partitions wholly below a block can be detached into standalone tables
+//! for archiving or dropping.
+
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+/// Blocks covered by each partition, as laid out by migration 014
+pub const PARTITION_BLOCKS: i64 = 1_000_000;
+
+/// Tables range-partitioned by block number
+pub const PARTITIONED_TABLES: [&str; 2] = ["data_submissions", "device_events"];
+
+/// The partitioned table whose old partitions can be detached
+const DETACHABLE_TABLE: &str = "data_submissions";
+
+/// Partition ranges already created, so DDL is only issued when the indexer
+/// reaches a new range
+#[derive(Default)]
+pub struct Partitions {
+    created: Mutex<HashSet<i64>>,
+}
+
+impl Partitions {
+    /// Make sure every partitioned table has a partition for `block` and for
+    /// the range after it
+    pub async fn ensure(&self, db: &Pool<Postgres>, block: i64) -> sqlx::Result<()> {
+        let range = block / PARTITION_BLOCKS;
+        let mut created = self.created.lock().await;
+    
+        for range in [range, range + 1] {
+            if created.contains(&range) {
+                continue;
+            }
+    
+            for table in PARTITIONED_TABLES {
+                let partition: String =
+                    sqlx::query_scalar("SELECT ensure_block_partition($1, $2, $3)")
+                        .bind(table)
+                        .bind(range * PARTITION_BLOCKS)
+                        .bind(PARTITION_BLOCKS)
+                        .fetch_one(db)
+                        .await?;
+                debug!("Partition {} ready", partition);
+            }
+            created.insert(range);
+        }
+    
+        Ok(())
+    }
+}
+
+/// Detach the `data_submissions` partitions whose blocks all lie below
+/// `block`, returning the standalone tables they became
+///
+/// Each detached table is renamed `<partition>_detached_<UTC time>`, so the
+/// indexer can create the partition afresh should it store into that range
+/// again, and is recorded in `archives` with its name as the path.
+/// `device_events` partitions stay attached: they hold the registrations
+/// devices are resolved from, and retention archives the rest of them.
+pub async fn detach_before(db: &Pool<Postgres>, block: i64) -> Result<Vec<String>> {
+    let table = DETACHABLE_TABLE;
+    let partitions: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT c.relname::TEXT
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = $1::regclass
+        ORDER BY c.relname
+        "#
+    )
+    .bind(table)
+    .fetch_all(db)
+    .await?;
+    
+    let suffix = Utc::now().format("%Y%m%d%H%M%S");
+    let mut detached = Vec::new();
+    for partition in partitions {
+        let Some(range) = partition
+            .strip_prefix(table)
+            .and_then(|s| s.strip_prefix("_p"))
+            .and_then(|s| s.parse::<i64>().ok())
+        else {
+            continue;
+        };
+        if (range + 1) * PARTITION_BLOCKS > block {
+            continue;
+        }
+    
+        let renamed = format!("{}_detached_{}", partition, suffix);
+        let mut tx = db.begin().await?;
+        sqlx::query(&format!(r#"ALTER TABLE "{}" DETACH PARTITION "{}""#, table, partition))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(r#"ALTER TABLE "{}" RENAME TO "{}""#, partition, renamed))
+            .execute(&mut *tx)
+            .await?;
+        // An empty partition is recorded with its range and no rows
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO archives (
+                table_name, path, row_count, from_block, to_block, from_timestamp, to_timestamp
+            )
+            SELECT
+                $1, $2, COUNT(*),
+                COALESCE(MIN(block_number), $3), COALESCE(MAX(block_number), $4),
+                COALESCE(MIN(timestamp), 0), COALESCE(MAX(timestamp), 0)
+            FROM "{}"
+            "#,
+            renamed
+        ))
+        .bind(table)
+        .bind(&renamed)
+        .bind(range * PARTITION_BLOCKS)
+        .bind((range + 1) * PARTITION_BLOCKS - 1)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        info!("Detached partition {} from {} as {}", partition, table, renamed);
+        detached.push(renamed);
+    }
+    
+    Ok(detached)
+}
@@ -0,0 +1,50 @@
+//! Read replica lag tracking
+//!
+//! The indexer saves how far each contract is indexed in `indexing_progress`
+//! on the primary (see `checkpoint`); once the replica has replayed an entry
+//! it has replayed every row up to that block. The API caps the blocks it
+//! reports at what the replica has, so it never points at rows a read cannot
+//! see yet.
+
+use crate::{status::CONTRACTS, AppState};
+use sqlx::{Pool, Postgres};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+/// How often progress is read back from the replica
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Follow the replica's copy of the indexing progress until the process exits
+pub async fn run_monitor(state: Arc<AppState>, replica: Pool<Postgres>) {
+    let mut ticks = tokio::time::interval(CHECK_INTERVAL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    
+    loop {
+        ticks.tick().await;
+    
+        match replica_progress(&replica).await {
+            Ok(blocks) => {
+                for (contract, block) in &blocks {
+                    state.metrics.replica_block.with_label_values(&[contract]).set(*block as i64);
+                }
+                state.status.write().await.replica_blocks = Some(blocks);
+            }
+            Err(e) => warn!("Failed to read indexing progress from the replica: {}", e),
+        }
+    }
+}
+
+async fn replica_progress(db: &Pool<Postgres>) -> sqlx::Result<BTreeMap<&'static str, u64>> {
+    let rows: Vec<(String, i64)> = sqlx::query_as("SELECT contract, indexed_block FROM indexing_progress")
+        .fetch_all(db)
+        .await?;
+    
+    Ok(rows
+        .into_iter()
+        .filter_map(|(contract, block)| {
+            let contract = CONTRACTS.iter().find(|c| **c == contract)?;
+            Some((*contract, u64::try_from(block).ok()?))
+        })
+        .collect())
+}
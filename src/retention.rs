@@ -0,0 +1,161 @@
+//! Data retention
+//!
+//! A background job moves rows older than their table's configured
+//! retention out of the database. Each batch is written to a Parquet file
+//! under the archive directory, then deleted and recorded in `archives` in
+//! one transaction, so a row is only removed once its archive is on disk.
+
+use crate::{
+    config::Config,
+    export::{self, ExportRow, ExportTable},
+    models::{DataSubmission, DeviceEvent},
+    AppState,
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use clap::ValueEnum;
+use sqlx::{Pool, Postgres};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// Rows written to each archive file
+const ARCHIVE_BATCH_ROWS: i64 = 10_000;
+
+/// Device registrations are never archived: which devices exist, and who
+/// registered them, is derived from them
+const ARCHIVABLE_DEVICE_EVENTS: &str = "event_type <> 'registered'";
+
+/// Rows of `table` whose event timestamp is older than `days` are archived
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub table: ExportTable,
+    pub days: u32,
+}
+
+/// The configured retention policies, rejecting tables that cannot be archived
+pub fn policies(config: &Config) -> Result<Vec<RetentionPolicy>> {
+    config
+        .retention_days
+        .iter()
+        .map(|(name, days)| {
+            let Ok(table) = ExportTable::from_str(name, false) else {
+                bail!("Retention is not supported for table {:?}", name);
+            };
+            if days == 0 {
+                bail!("Retention for {} must be at least one day", name);
+            }
+            Ok(RetentionPolicy { table, days })
+        })
+        .collect()
+}
+
+/// Archive expired rows until the process exits
+pub async fn run_archiver(state: Arc<AppState>, db: Pool<Postgres>, policies: Vec<RetentionPolicy>) {
+    let period = Duration::from_secs(state.config.retention_check_interval_secs);
+    let mut ticks = tokio::time::interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let archive_dir = PathBuf::from(&state.config.archive_dir);
+    
+    loop {
+        ticks.tick().await;
+    
+        for policy in &policies {
+            let cutoff = Utc::now().timestamp() - i64::from(policy.days) * 86_400;
+            let (name, archived) = match policy.table {
+                ExportTable::DataSubmissions => (
+                    "data_submissions",
+                    archive_before::<DataSubmission>(&db, &archive_dir, cutoff, "TRUE").await,
+                ),
+                ExportTable::DeviceEvents => (
+                    "device_events",
+                    archive_before::<DeviceEvent>(&db, &archive_dir, cutoff, ARCHIVABLE_DEVICE_EVENTS).await,
+                ),
+            };
+            if let Err(e) = archived {
+                warn!("Archiving {} failed: {:#}", name, e);
+            }
+        }
+    }
+}
+
+/// Move the rows of `T` matching `archivable` with an event timestamp
+/// before `cutoff` into archive files
+async fn archive_before<T: ExportRow>(
+    db: &Pool<Postgres>,
+    archive_dir: &Path,
+    cutoff: i64,
+    archivable: &str,
+) -> Result<()> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE timestamp < $1 AND ({}) ORDER BY id LIMIT $2",
+        T::COLUMNS,
+        T::TABLE,
+        archivable,
+    );
+
+    loop {
+        let rows = sqlx::query_as::<_, T>(&sql)
+            .bind(cutoff)
+            .bind(ARCHIVE_BATCH_ROWS)
+            .fetch_all(db)
+            .await?;
+        let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+            return Ok(());
+        };
+    
+        let path = archive_dir
+            .join(T::TABLE)
+            .join(format!("{}-{}-{}.parquet", T::TABLE, first.id(), last.id()));
+        let file_path = path.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            export::write_parquet_file(&file_path, &rows).map(|()| rows)
+        })
+        .await
+        .context("Archive writer panicked")??;
+    
+        let ids: Vec<i64> = rows.iter().map(ExportRow::id).collect();
+        let from_block = rows.iter().map(ExportRow::block_number).min().unwrap_or_default();
+        let to_block = rows.iter().map(ExportRow::block_number).max().unwrap_or_default();
+        let from_timestamp = rows.iter().map(ExportRow::timestamp).min().unwrap_or_default();
+        let to_timestamp = rows.iter().map(ExportRow::timestamp).max().unwrap_or_default();
+    
+        let mut tx = db.begin().await?;
+        // The block bounds let the delete skip partitions outside the batch
+        let deleted = sqlx::query(&format!(
+            "DELETE FROM {} WHERE id = ANY($1) AND block_number BETWEEN $2 AND $3",
+            T::TABLE,
+        ))
+        .bind(&ids)
+        .bind(from_block)
+        .bind(to_block)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    
+        sqlx::query(
+            r#"
+            INSERT INTO archives (
+                table_name, path, row_count, from_block, to_block, from_timestamp, to_timestamp
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#
+        )
+        .bind(T::TABLE)
+        .bind(path.display().to_string())
+        .bind(deleted as i64)
+        .bind(from_block)
+        .bind(to_block)
+        .bind(from_timestamp)
+        .bind(to_timestamp)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        info!("Archived {} {} rows to {}", deleted, T::TABLE, path.display());
+    }
+}
@@ -0,0 +1,273 @@
+//! Indexing progress shared between the indexer and the API
+
+use std::collections::BTreeMap;
+
+/// Contract names used as keys for per-contract progress
+pub const VERIFIER_REGISTRY: &str = "verifier_registry";
+pub const DEVICE_REGISTRY: &str = "device_registry";
+pub const IOT_PIPELINE: &str = "iot_pipeline";
+
+pub const CONTRACTS: [&str; 3] = [VERIFIER_REGISTRY, DEVICE_REGISTRY, IOT_PIPELINE];
+
+#[derive(Debug, Clone, Default)]
+pub struct ContractProgress {
+    /// Last block whose logs have been processed for this contract
+    pub indexed_block: u64,
+    /// Timestamp of `indexed_block`, when known
+    pub indexed_block_timestamp: Option<i64>,
+    /// Whether the live log subscription is currently open
+    pub live: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct IndexerStatus {
+    /// Whether the indexer task is still running
+    pub running: bool,
+    pub chain_head: u64,
+    pub chain_head_timestamp: Option<i64>,
+    pub contracts: BTreeMap<&'static str, ContractProgress>,
+    /// Last block per contract the API's read replica has caught up to, when
+    /// it reads from one
+    pub replica_blocks: Option<BTreeMap<&'static str, u64>>,
+}
+
+impl IndexerStatus {
+    /// Record a new chain head announced by the node; `timestamp` is `None`
+    /// when the node reports one that does not fit in an `i64`
+    pub fn record_head(&mut self, number: u64, timestamp: Option<i64>) {
+        // A contract only goes live once its backfill has reached the head,
+        // and its subscription has no backlog after that: by the time the
+        // node announces a new head, every log from the previous head has
+        // been delivered, so quiet contracts are caught up to it as well
+        for progress in self.contracts.values_mut().filter(|p| p.live) {
+            if self.chain_head > progress.indexed_block {
+                progress.indexed_block = self.chain_head;
+                progress.indexed_block_timestamp = self.chain_head_timestamp;
+            }
+        }
+    
+        self.chain_head = number;
+        self.chain_head_timestamp = timestamp;
+    }
+    
+    /// Record that a log from `block` has been processed for `contract`
+    pub fn record_log(&mut self, contract: &'static str, block: u64) {
+        let head = self.chain_head;
+        let head_timestamp = self.chain_head_timestamp;
+        let progress = self.contracts.entry(contract).or_default();
+    
+        if block > progress.indexed_block {
+            progress.indexed_block = block;
+            progress.indexed_block_timestamp = if block == head { head_timestamp } else { None };
+        }
+    }
+    
+    /// Mark whether the log subscription for `contract` is open; progress
+    /// only advances through `record_log`, so a contract that has just
+    /// subscribed is not counted as caught up before its backfill completes
+    pub fn set_live(&mut self, contract: &'static str, live: bool) {
+        self.contracts.entry(contract).or_default().live = live;
+    }
+    
+    /// `progress` as far as the API can read it: capped at the block the
+    /// read replica has caught up to, when there is one
+    pub fn readable(&self, contract: &str, progress: &ContractProgress) -> ContractProgress {
+        let Some(replica_blocks) = &self.replica_blocks else {
+            return progress.clone();
+        };
+        let replica_block = replica_blocks.get(contract).copied().unwrap_or(0);
+        
+        if replica_block >= progress.indexed_block {
+            return progress.clone();
+        }
+        ContractProgress {
+            indexed_block: replica_block,
+            indexed_block_timestamp: None,
+            live: progress.live,
+        }
+    }
+    
+    /// `block` capped at the newest block the read replica has caught up to,
+    /// when there is one
+    pub fn readable_block(&self, block: u64) -> u64 {
+        match &self.replica_blocks {
+            Some(replica_blocks) => block.min(replica_blocks.values().copied().max().unwrap_or(0)),
+            None => block,
+        }
+    }
+    
+    /// Blocks between the chain head and the last indexed block for `contract`
+    pub fn lag_blocks(&self, progress: &ContractProgress) -> u64 {
+        self.chain_head.saturating_sub(progress.indexed_block)
+    }
+    
+    /// Seconds between the chain head and the last indexed block, when both
+    /// timestamps are known
+    pub fn lag_seconds(&self, progress: &ContractProgress) -> Option<i64> {
+        if self.lag_blocks(progress) == 0 {
+            return Some(0);
+        }
+    
+        match (self.chain_head_timestamp, progress.indexed_block_timestamp) {
+            (Some(head), Some(indexed)) => Some((head - indexed).max(0)),
+            _ => None,
+        }
+    }
+    
+    /// Why each contract is not healthy: not subscribed, subscription closed,
+    /// or further than `max_lag` blocks behind the chain head. Lag counts
+    /// from what the API can read, which a replica may trail
+    pub fn indexing_problems(&self, max_lag: u64) -> Vec<String> {
+        CONTRACTS
+            .iter()
+            .filter_map(|contract| {
+                let progress = self.contracts.get(contract).map(|p| self.readable(contract, p));
+                match progress {
+                    None => Some(format!("{} is not subscribed yet", contract)),
+                    Some(progress) if !progress.live => Some(format!("{} subscription is closed", contract)),
+                    Some(progress) if self.lag_blocks(&progress) > max_lag => Some(format!(
+                        "{} is {} blocks behind the chain head (max {})",
+                        contract,
+                        self.lag_blocks(&progress),
+                        max_lag
+                    )),
+                    Some(_) => None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn progress(indexed_block: u64, indexed_block_timestamp: Option<i64>) -> ContractProgress {
+        ContractProgress { indexed_block, indexed_block_timestamp, live: true }
+    }
+    
+    /// A status at head 100 (timestamp 1_000) with every contract live at `block`
+    fn caught_up_to(block: u64) -> IndexerStatus {
+        let mut status = IndexerStatus {
+            running: true,
+            chain_head: 100,
+            chain_head_timestamp: Some(1_000),
+            ..Default::default()
+        };
+        for contract in CONTRACTS {
+            status.contracts.insert(contract, progress(block, None));
+        }
+        status
+    }
+    
+    #[test]
+    fn lag_counts_from_the_chain_head() {
+        let status = caught_up_to(90);
+        
+        assert_eq!(status.lag_blocks(&progress(90, None)), 10);
+        assert_eq!(status.lag_blocks(&progress(100, None)), 0);
+        // A contract ahead of a stale head is not behind
+        assert_eq!(status.lag_blocks(&progress(105, None)), 0);
+    }
+    
+    #[test]
+    fn lag_seconds_needs_both_timestamps_unless_caught_up() {
+        let status = caught_up_to(90);
+        
+        assert_eq!(status.lag_seconds(&progress(90, Some(880))), Some(120));
+        assert_eq!(status.lag_seconds(&progress(90, None)), None);
+        assert_eq!(status.lag_seconds(&progress(100, None)), Some(0));
+        // Out-of-order timestamps never report a negative lag
+        assert_eq!(status.lag_seconds(&progress(90, Some(1_200))), Some(0));
+    }
+    
+    #[test]
+    fn record_log_keeps_the_furthest_block() {
+        let mut status = caught_up_to(0);
+        
+        status.record_log(DEVICE_REGISTRY, 100);
+        status.record_log(DEVICE_REGISTRY, 95);
+        let progress = &status.contracts[DEVICE_REGISTRY];
+        assert_eq!(progress.indexed_block, 100);
+        assert_eq!(progress.indexed_block_timestamp, Some(1_000));
+        
+        // Only the head's timestamp is known
+        status.record_log(IOT_PIPELINE, 99);
+        assert_eq!(status.contracts[IOT_PIPELINE].indexed_block_timestamp, None);
+    }
+    
+    #[test]
+    fn a_new_head_catches_live_contracts_up_to_the_previous_one() {
+        let mut status = caught_up_to(90);
+        status.set_live(IOT_PIPELINE, false);
+        
+        status.record_head(101, Some(1_012));
+        
+        assert_eq!(status.contracts[DEVICE_REGISTRY].indexed_block, 100);
+        assert_eq!(status.contracts[DEVICE_REGISTRY].indexed_block_timestamp, Some(1_000));
+        assert_eq!(status.contracts[IOT_PIPELINE].indexed_block, 90);
+        assert_eq!(status.lag_blocks(&status.contracts[DEVICE_REGISTRY]), 1);
+    }
+    
+    #[test]
+    fn readable_progress_is_capped_at_the_replica() {
+        let mut status = caught_up_to(100);
+        let primary = progress(100, Some(1_000));
+        assert_eq!(status.readable(DEVICE_REGISTRY, &primary).indexed_block, 100);
+        assert_eq!(status.readable_block(100), 100);
+        
+        status.replica_blocks = Some(BTreeMap::from([(DEVICE_REGISTRY, 80), (IOT_PIPELINE, 120)]));
+        
+        let readable = status.readable(DEVICE_REGISTRY, &primary);
+        assert_eq!(readable.indexed_block, 80);
+        assert_eq!(readable.indexed_block_timestamp, None);
+        assert_eq!(status.lag_blocks(&readable), 20);
+        // A replica ahead of the primary does not move progress forward
+        assert_eq!(status.readable(IOT_PIPELINE, &primary).indexed_block, 100);
+        // A contract the replica has not reported yet reads as block 0
+        assert_eq!(status.readable(VERIFIER_REGISTRY, &primary).indexed_block, 0);
+        assert_eq!(status.readable_block(150), 120);
+    }
+    
+    #[test]
+    fn healthy_within_max_lag() {
+        let status = caught_up_to(50);
+        
+        assert!(status.indexing_problems(50).is_empty());
+        assert_eq!(status.indexing_problems(49).len(), CONTRACTS.len());
+        assert_eq!(
+            status.indexing_problems(49)[0],
+            "verifier_registry is 50 blocks behind the chain head (max 49)"
+        );
+    }
+    
+    #[test]
+    fn unsubscribed_and_closed_contracts_are_unhealthy() {
+        let mut status = caught_up_to(100);
+        status.contracts.remove(VERIFIER_REGISTRY);
+        status.set_live(IOT_PIPELINE, false);
+        
+        assert_eq!(
+            status.indexing_problems(50),
+            vec![
+                "verifier_registry is not subscribed yet".to_string(),
+                "iot_pipeline subscription is closed".to_string(),
+            ]
+        );
+    }
+    
+    #[test]
+    fn replica_lag_counts_against_health() {
+        let mut status = caught_up_to(100);
+        status.replica_blocks = Some(BTreeMap::from([
+            (VERIFIER_REGISTRY, 100),
+            (DEVICE_REGISTRY, 40),
+            (IOT_PIPELINE, 100),
+        ]));
+        
+        assert_eq!(
+            status.indexing_problems(50),
+            vec!["device_registry is 60 blocks behind the chain head (max 50)".to_string()]
+        );
+    }
+}
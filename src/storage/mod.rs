@@ -0,0 +1,410 @@
+//! Storage backends
+//!
+//! The indexer's event writes and the REST API's reads go through
+//! [`Storage`], implemented for PostgreSQL and SQLite and chosen by the
+//! scheme of `database_url`. PostgreSQL is the full backend; SQLite keeps the
+//! same events and answers the same reads for small deployments, but not the
+//! features built on PostgreSQL (GraphQL, the live stream and webhooks,
+//! alerts, submission analytics, export, partitions and retention).
+
+mod postgres;
+pub(crate) mod sql;
+mod sqlite;
+
+pub use postgres::PgStorage;
+pub use sqlite::SqliteStorage;
+
+use crate::{
+    config::Config,
+    liveness::StaleCutoffs,
+    models::*,
+    LogMeta,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use ethers::core::types::Log;
+use sqlx::{pool::PoolOptions, Pool, Postgres};
+use std::{sync::Arc, time::Duration};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+const WEEK_SECS: i64 = 7 * DAY_SECS;
+
+/// How a connection pool is sized and how long its connections and
+/// statements may wait or run
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+    /// Applied by PostgreSQL only
+    pub statement_timeout: Option<Duration>,
+}
+
+impl PoolSettings {
+    fn new(config: &Config, max_connections: u32) -> Self {
+        let secs = |secs| (secs > 0).then(|| Duration::from_secs(secs));
+        Self {
+            max_connections,
+            min_connections: config.database_min_connections,
+            acquire_timeout: Duration::from_secs(config.database_acquire_timeout_secs),
+            idle_timeout: secs(config.database_idle_timeout_secs),
+            statement_timeout: secs(config.database_statement_timeout_secs),
+        }
+    }
+    
+    /// A single connection, so an in-memory SQLite database is the same one
+    /// for every query
+    #[cfg(test)]
+    pub(crate) fn for_tests() -> Self {
+        Self {
+            max_connections: 1,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(10),
+            idle_timeout: None,
+            statement_timeout: None,
+        }
+    }
+    
+    fn pool_options<DB: sqlx::Database>(&self) -> PoolOptions<DB> {
+        PoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+    }
+}
+
+/// LIMIT and OFFSET of a page of rows
+#[derive(Debug, Clone, Copy)]
+pub struct Paging {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// One page of rows, with the number of rows across all pages
+#[derive(Debug)]
+pub struct Page<T> {
+    pub rows: Vec<T>,
+    pub total: i64,
+}
+
+/// Devices to list; unset fields match every device
+#[derive(Debug, Default)]
+pub struct DeviceFilter<'a> {
+    pub status: Option<DeviceStatus>,
+    pub zone: Option<&'a str>,
+}
+
+/// Indexed totals and recent submission activity for `/stats`
+#[derive(Debug, Default)]
+pub struct IndexedCounts {
+    pub verifiers: i64,
+    pub devices: i64,
+    pub data_submissions: i64,
+    pub submissions_24h: i64,
+    pub submissions_7d: i64,
+    pub active_devices_24h: i64,
+    pub active_devices_7d: i64,
+}
+
+/// A `DeviceRegistered` event
+#[derive(Debug)]
+pub struct NewDevice<'a> {
+    pub device_id: HexBytes32,
+    pub owner: HexAddress,
+    pub device_type: i32,
+    pub zone: &'a str,
+    pub timestamp: i64,
+}
+
+/// A `DataSubmitted` event
+#[derive(Debug)]
+pub struct NewSubmission {
+    pub data_hash: HexBytes32,
+    pub device_id_hash: HexBytes32,
+    pub owner: HexAddress,
+    pub timestamp: i64,
+}
+
+/// Where indexed events are written and the REST API reads them from
+///
+/// Writes whose events go out on the live stream return the stream event
+/// they appended, for the caller to announce; backends without a stream
+/// return `None`. Each write is keyed by the transaction hash and log index
+/// in its `LogMeta`, and a log that is already stored is skipped, returning
+/// `None`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Check the database answers
+    async fn ping(&self) -> sqlx::Result<()>;
+    
+    /// Connections open and connections idle in the pool
+    fn pool_usage(&self) -> (u32, usize);
+    
+    /// Get ready to store events from `block`
+    async fn prepare_block(&self, _block: i64) -> sqlx::Result<()> {
+        Ok(())
+    }
+    
+    async fn add_verifier(
+        &self,
+        verifier: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<Option<StreamEvent>>;
+
+    async fn remove_verifier(&self, verifier: HexAddress, timestamp: i64, meta: &LogMeta) -> sqlx::Result<()>;
+    
+    async fn transfer_ownership(
+        &self,
+        contract_type: &str,
+        previous_owner: HexAddress,
+        new_owner: HexAddress,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()>;
+
+    async fn register_device(&self, device: &NewDevice<'_>, meta: &LogMeta) -> sqlx::Result<Option<StreamEvent>>;
+    
+    async fn update_device(
+        &self,
+        device_id: HexBytes32,
+        owner: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()>;
+
+    async fn transfer_device(
+        &self,
+        device_id: HexBytes32,
+        old_owner: HexAddress,
+        new_owner: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()>;
+
+    async fn submit_data(&self, submission: &NewSubmission, meta: &LogMeta) -> sqlx::Result<Option<StreamEvent>>;
+    
+    /// Record a base fee given as a decimal string, since a uint256 can
+    /// overflow every integer type
+    async fn update_marketplace_config(&self, base_fee: &str, meta: &LogMeta) -> sqlx::Result<()>;
+    
+    /// Record a log indexing failed on, or count another attempt at one
+    /// already recorded
+    async fn record_failed_log(
+        &self,
+        contract: &str,
+        log: &Log,
+        kind: FailureKind,
+        error: &str,
+    ) -> sqlx::Result<()>;
+    
+    /// Record that `contract` is indexed up to `block`; a saved block never
+    /// moves back
+    async fn save_progress(&self, contract: &str, block: i64) -> sqlx::Result<()>;
+    
+    /// The last indexed block saved for each contract
+    async fn saved_progress(&self) -> sqlx::Result<Vec<(String, i64)>>;
+    
+    async fn counts(&self, now: i64) -> sqlx::Result<IndexedCounts>;
+    
+    async fn verifiers(
+        &self,
+        as_of_block: Option<i64>,
+        active: Option<bool>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<VerifierInfo>>;
+
+    async fn verifier_events(
+        &self,
+        address: HexAddress,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<VerifierEvent>>;
+
+    async fn devices(
+        &self,
+        as_of_block: Option<i64>,
+        filter: &DeviceFilter<'_>,
+        cutoffs: &StaleCutoffs,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceInfo>>;
+
+    async fn device(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        cutoffs: &StaleCutoffs,
+    ) -> sqlx::Result<Option<DeviceInfo>>;
+
+    async fn device_events(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceEvent>>;
+
+    /// A device's chain of custody, oldest holder first
+    async fn device_owners(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceOwnership>>;
+
+    /// The holder of a device at `as_of_block`
+    async fn device_owner(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+    ) -> sqlx::Result<Option<DeviceOwnership>>;
+
+    async fn device_transfers(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceTransfer>>;
+
+    /// Data submissions, newest first, for one device ID hash or all of them
+    async fn data_submissions(
+        &self,
+        device_id_hash: Option<HexBytes32>,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DataSubmission>>;
+
+    async fn ownership_transfers(
+        &self,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<OwnershipTransfer>>;
+
+    /// Every marketplace fee update, newest first
+    async fn marketplace_fees(&self, as_of_block: Option<i64>) -> sqlx::Result<Vec<MarketplaceConfig>>;
+    
+    /// Per-zone device counts, with activity in the hours starting at or after `since`
+    async fn zone_stats(&self, since: i64) -> sqlx::Result<Vec<ZoneStats>>;
+    
+    /// Per-device-type device counts, with activity in the hours starting at
+    /// or after `since`
+    async fn device_type_stats(&self, since: i64) -> sqlx::Result<Vec<DeviceTypeStats>>;
+    
+    /// Archived rows of `table` at or below `as_of_block`, if there are any
+    async fn archived_rows(&self, _table: &str, _as_of_block: Option<i64>) -> sqlx::Result<Option<ArchivedRows>> {
+        Ok(None)
+    }
+    
+    async fn failed_events(
+        &self,
+        contract: Option<&str>,
+        kind: Option<FailureKind>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<FailedEvent>>;
+    
+    async fn failed_event(&self, id: i64) -> sqlx::Result<Option<FailedEvent>>;
+    
+    /// Count another failed attempt at a failed event
+    async fn record_failed_retry(&self, id: i64, error: &str) -> sqlx::Result<Option<FailedEvent>>;
+    
+    /// Remove a failed event, returning whether there was one
+    async fn discard_failed_event(&self, id: i64) -> sqlx::Result<bool>;
+}
+
+/// An open database: the storage the indexer writes to and the storage the
+/// API reads from, with their pools when the backend is PostgreSQL
+pub struct Database {
+    pub storage: Arc<dyn Storage>,
+    pub reads: Arc<dyn Storage>,
+    pub postgres: Option<Pool<Postgres>>,
+    pub postgres_reads: Option<Pool<Postgres>>,
+}
+
+/// Statement giving the index `$3` of the log at block `$1` in transaction
+/// `$2` to a row of `table` stored for it before log indexes were recorded,
+/// where `matches` compares the row's own columns with `$4` on. Those rows
+/// keep a NULL index, which the unique keys never match, so a log delivered
+/// again claims its old row instead of being stored a second time.
+fn claim_unindexed(table: &str, matches: &str) -> String {
+    format!(
+        r#"
+        UPDATE {table} SET log_index = $3
+        WHERE block_number = $1 AND id = (
+            SELECT id FROM {table}
+            WHERE block_number = $1 AND tx_hash = $2 AND log_index IS NULL AND {matches}
+                AND NOT EXISTS (
+                    SELECT 1 FROM {table} WHERE block_number = $1 AND tx_hash = $2 AND log_index = $3
+                )
+            ORDER BY id
+            LIMIT 1
+        )
+        "#
+    )
+}
+
+fn is_postgres(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// Connect to `database_url` with the backend its scheme names, bring its
+/// schema up to date, and open the read pool on `database_read_url` or,
+/// without one, on `database_url` again
+pub async fn connect(config: &Config) -> Result<Database> {
+    let url = &config.database_url;
+    let read_url = config.database_read_url.as_deref();
+    let writes = PoolSettings::new(config, config.database_max_connections);
+    let reads = PoolSettings::new(config, config.database_read_max_connections);
+    
+    if is_postgres(url) {
+        if read_url.is_some_and(|read_url| !is_postgres(read_url)) {
+            bail!("database_read_url must start with postgres:// or postgresql://");
+        }
+    
+        let storage = PgStorage::connect(url, &writes).await?;
+        let reads = PgStorage::connect_reads(read_url.unwrap_or(url), &reads).await?;
+        Ok(Database {
+            postgres: Some(storage.pool().clone()),
+            postgres_reads: Some(reads.pool().clone()),
+            storage: Arc::new(storage),
+            reads: Arc::new(reads),
+        })
+    } else if url.starts_with("sqlite:") {
+        if read_url.is_some() {
+            bail!("database_read_url requires the PostgreSQL backend");
+        }
+    
+        let storage = SqliteStorage::connect(url, &writes).await?;
+        let reads = SqliteStorage::connect_reads(url, &reads).await?;
+        Ok(Database {
+            storage: Arc::new(storage),
+            reads: Arc::new(reads),
+            postgres: None,
+            postgres_reads: None,
+        })
+    } else {
+        bail!("database_url must start with postgres://, postgresql:// or sqlite:");
+    }
+}
+
+/// A new schema in the PostgreSQL database `TEST_DATABASE_URL` names,
+/// migrated, for the tests that need one; those are ignored unless run with
+/// `--ignored`. Each call gets a schema of its own, so tests running at once
+/// do not see each other's rows or counters.
+#[cfg(test)]
+pub(crate) async fn test_postgres() -> PgStorage {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static SCHEMAS: AtomicU32 = AtomicU32::new(0);
+    
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+    let schema = format!("test_{}_{}", std::process::id(), SCHEMAS.fetch_add(1, Ordering::Relaxed));
+    let db = Pool::<Postgres>::connect(&url).await.expect("Failed to open the test database");
+    for statement in [format!("DROP SCHEMA IF EXISTS {schema} CASCADE"), format!("CREATE SCHEMA {schema}")] {
+        sqlx::query(&statement).execute(&db).await.expect("Failed to create a test schema");
+    }
+    db.close().await;
+    
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let url = format!("{url}{separator}options[search_path]={schema}");
+    let settings = PoolSettings { max_connections: 4, ..PoolSettings::for_tests() };
+    PgStorage::connect(&url, &settings).await.expect("Failed to migrate the test schema")
+}
@@ -0,0 +1,1125 @@
+//! PostgreSQL storage
+//!
+//! Streamed events are appended to `event_stream` in the same transaction
+//! as their rows, and writes to the partitioned tables create the
+//! partitions they need first.
+
+use super::{
+    claim_unindexed,
+    sql::{DEVICE_CUSTODY_CTE, DEVICE_STATE_CTE, MARKETPLACE_FEES_CTE, VERIFIER_STATE_CTE},
+    DeviceFilter, IndexedCounts, NewDevice, NewSubmission, Page, Paging, PoolSettings, Storage,
+};
+use crate::{
+    analytics,
+    liveness::{StaleCutoffs, DEVICE_LIVENESS_CTE},
+    models::*,
+    partitions::Partitions,
+    status,
+    stream::{self, NewStreamEvent},
+    LogMeta,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use ethers::core::types::Log;
+use sqlx::{postgres::PgConnectOptions, types::Json, Pool, Postgres};
+use std::{collections::HashMap, str::FromStr};
+use tracing::info;
+
+/// SELECT list for `FailedEvent`, with numeric columns as text
+const FAILED_EVENT_COLUMNS: &str = r#"
+    id, contract, topic, block_number::text AS block_number, log_index::text AS log_index,
+    tx_hash, kind, error, attempts, raw_log, last_attempt_at, created_at
+"#;
+
+/// Failed events of contract `$1` and kind `$2`, either unset for all
+const FAILED_EVENT_FILTER: &str = "($1::TEXT IS NULL OR contract = $1) AND ($2::TEXT IS NULL OR kind = $2)";
+
+pub struct PgStorage {
+    db: Pool<Postgres>,
+    partitions: Partitions,
+}
+
+impl PgStorage {
+    /// Connect and run the migrations in `migrations/`
+    pub async fn connect(url: &str, settings: &PoolSettings) -> anyhow::Result<Self> {
+        let db = settings
+            .pool_options()
+            .connect_with(connect_options(url, settings)?)
+            .await
+            .context("Failed to connect to database")?;
+    
+        info!("Connected to PostgreSQL database");
+    
+        sqlx::migrate!("./migrations")
+            .run(&db)
+            .await
+            .context("Failed to run migrations")?;
+    
+        info!("Database migrations complete");
+    
+        Ok(Self { db, partitions: Partitions::default() })
+    }
+    
+    /// Connect for reads only, to the database itself or a replica of it
+    pub async fn connect_reads(url: &str, settings: &PoolSettings) -> anyhow::Result<Self> {
+        let db = settings
+            .pool_options()
+            .connect_with(connect_options(url, settings)?)
+            .await
+            .context("Failed to connect to read database")?;
+    
+        info!("Connected to PostgreSQL read database");
+    
+        Ok(Self { db, partitions: Partitions::default() })
+    }
+    
+    pub fn pool(&self) -> &Pool<Postgres> {
+        &self.db
+    }
+}
+
+/// `url` with the statement timeout `settings` asks for, if any
+fn connect_options(url: &str, settings: &PoolSettings) -> anyhow::Result<PgConnectOptions> {
+    let options = PgConnectOptions::from_str(url).context("Invalid PostgreSQL database URL")?;
+    
+    Ok(match settings.statement_timeout {
+        Some(timeout) => options.options([("statement_timeout", format!("{}ms", timeout.as_millis()))]),
+        None => options,
+    })
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    async fn ping(&self) -> sqlx::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.db).await?;
+        Ok(())
+    }
+    
+    fn pool_usage(&self) -> (u32, usize) {
+        (self.db.size(), self.db.num_idle())
+    }
+    
+    async fn prepare_block(&self, block: i64) -> sqlx::Result<()> {
+        self.partitions.ensure(&self.db, block).await
+    }
+    
+    async fn add_verifier(
+        &self,
+        verifier: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<Option<StreamEvent>> {
+        let mut tx = self.db.begin().await?;
+        stream::lock(&mut tx).await?;
+    
+        let claimed = sqlx::query(&claim_unindexed("verifier_events", "verifier_address = $4 AND event_type = 'added'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(verifier)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return stream::commit(tx, None).await;
+        }
+    
+        let payload: Option<serde_json::Value> = sqlx::query_scalar(
+            r#"
+            INSERT INTO verifier_events (verifier_address, event_type, timestamp, block_number, tx_hash, log_index)
+            VALUES ($1, 'added', $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            RETURNING to_jsonb(verifier_events.*) || jsonb_build_object(
+                'verifier_address', '0x' || encode(verifier_address, 'hex'),
+                'tx_hash', '0x' || encode(tx_hash, 'hex')
+            )
+            "#
+        )
+        .bind(verifier)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .fetch_optional(&mut *tx)
+        .await?;
+    
+        let streamed = payload.map(|payload| NewStreamEvent {
+            contract: status::VERIFIER_REGISTRY,
+            event_type: stream::VERIFIER_ADDED,
+            owner: None,
+            device: None,
+            block_number: meta.block_number(),
+            tx_hash: meta.tx_hash,
+            payload,
+        });
+        stream::commit(tx, streamed).await
+    }
+    
+    async fn remove_verifier(&self, verifier: HexAddress, timestamp: i64, meta: &LogMeta) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("verifier_events", "verifier_address = $4 AND event_type = 'removed'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(verifier)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO verifier_events (verifier_address, event_type, timestamp, block_number, tx_hash, log_index)
+            VALUES ($1, 'removed', $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(verifier)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+    
+        tx.commit().await
+    }
+    
+    async fn transfer_ownership(
+        &self,
+        contract_type: &str,
+        previous_owner: HexAddress,
+        new_owner: HexAddress,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("ownership_transfers", "contract_type = $4 AND new_owner = $5"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(contract_type)
+            .bind(new_owner)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO ownership_transfers (
+                contract_type, previous_owner, new_owner, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(contract_type)
+        .bind(previous_owner)
+        .bind(new_owner)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+    
+        tx.commit().await
+    }
+    
+    async fn register_device(&self, device: &NewDevice<'_>, meta: &LogMeta) -> sqlx::Result<Option<StreamEvent>> {
+        self.prepare_block(meta.block_number()).await?;
+    
+        let mut tx = self.db.begin().await?;
+        stream::lock(&mut tx).await?;
+    
+        let claimed = sqlx::query(&claim_unindexed("device_events", "device_id = $4 AND event_type = 'registered'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(device.device_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return stream::commit(tx, None).await;
+        }
+    
+        let payload: Option<serde_json::Value> = sqlx::query_scalar(
+            r#"
+            INSERT INTO device_events (
+                device_id, owner_address, event_type, device_type, zone,
+                timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, 'registered', $3, $4, $5, $6, $7, $8)
+            ON CONFLICT DO NOTHING
+            RETURNING to_jsonb(device_events.*) || jsonb_build_object(
+                'device_id', '0x' || encode(device_id, 'hex'),
+                'owner_address', '0x' || encode(owner_address, 'hex'),
+                'tx_hash', '0x' || encode(tx_hash, 'hex')
+            )
+            "#
+        )
+        .bind(device.device_id)
+        .bind(device.owner)
+        .bind(device.device_type)
+        .bind(device.zone)
+        .bind(device.timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .fetch_optional(&mut *tx)
+        .await?;
+        analytics::record_device_hash(&mut *tx, &device.device_id).await?;
+    
+        let streamed = payload.map(|payload| NewStreamEvent {
+            contract: status::DEVICE_REGISTRY,
+            event_type: stream::DEVICE_REGISTERED,
+            owner: Some(device.owner),
+            device: Some(device.device_id),
+            block_number: meta.block_number(),
+            tx_hash: meta.tx_hash,
+            payload,
+        });
+        stream::commit(tx, streamed).await
+    }
+    
+    async fn update_device(
+        &self,
+        device_id: HexBytes32,
+        owner: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()> {
+        self.prepare_block(meta.block_number()).await?;
+    
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("device_events", "device_id = $4 AND event_type = 'updated'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(device_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO device_events (
+                device_id, owner_address, event_type, timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, 'updated', $3, $4, $5, $6)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(device_id)
+        .bind(owner)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+    
+        tx.commit().await
+    }
+    
+    async fn transfer_device(
+        &self,
+        device_id: HexBytes32,
+        old_owner: HexAddress,
+        new_owner: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("device_transfers", "device_id = $4 AND new_owner = $5"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(device_id)
+            .bind(new_owner)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO device_transfers (
+                device_id, old_owner, new_owner, timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(device_id)
+        .bind(old_owner)
+        .bind(new_owner)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+    
+        tx.commit().await
+    }
+    
+    async fn submit_data(&self, submission: &NewSubmission, meta: &LogMeta) -> sqlx::Result<Option<StreamEvent>> {
+        self.prepare_block(meta.block_number()).await?;
+    
+        let mut tx = self.db.begin().await?;
+        stream::lock(&mut tx).await?;
+    
+        let claimed = sqlx::query(&claim_unindexed("data_submissions", "data_hash = $4"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(submission.data_hash)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return stream::commit(tx, None).await;
+        }
+    
+        let payload: Option<serde_json::Value> = sqlx::query_scalar(
+            r#"
+            INSERT INTO data_submissions (
+                data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT DO NOTHING
+            RETURNING to_jsonb(data_submissions.*) || jsonb_build_object(
+                'data_hash', '0x' || encode(data_hash, 'hex'),
+                'device_id_hash', '0x' || encode(device_id_hash, 'hex'),
+                'device_owner', '0x' || encode(device_owner, 'hex'),
+                'tx_hash', '0x' || encode(tx_hash, 'hex')
+            )
+            "#
+        )
+        .bind(submission.data_hash)
+        .bind(submission.device_id_hash)
+        .bind(submission.owner)
+        .bind(submission.timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .fetch_optional(&mut *tx)
+        .await?;
+    
+        let streamed = payload.map(|payload| NewStreamEvent {
+            contract: status::IOT_PIPELINE,
+            event_type: stream::DATA_SUBMITTED,
+            owner: Some(submission.owner),
+            device: Some(submission.device_id_hash),
+            block_number: meta.block_number(),
+            tx_hash: meta.tx_hash,
+            payload,
+        });
+        stream::commit(tx, streamed).await
+    }
+    
+    async fn update_marketplace_config(&self, base_fee: &str, meta: &LogMeta) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("marketplace_config", "base_fee = $4::NUMERIC"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(base_fee)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace_config (base_fee, updated_at, block_number, tx_hash, log_index)
+            VALUES ($1::NUMERIC, NOW(), $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(base_fee)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+    
+        tx.commit().await
+    }
+    
+    async fn record_failed_log(
+        &self,
+        contract: &str,
+        log: &Log,
+        kind: FailureKind,
+        error: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO failed_events (
+                contract, topic, block_number, log_index, tx_hash, kind, error, raw_log
+            )
+            VALUES ($1, $2, $3::NUMERIC, $4::NUMERIC, $5, $6, $7, $8)
+            ON CONFLICT (contract, tx_hash, log_index) DO UPDATE
+            SET kind = EXCLUDED.kind,
+                error = EXCLUDED.error,
+                attempts = failed_events.attempts + 1,
+                last_attempt_at = NOW()
+            "#
+        )
+        .bind(contract)
+        .bind(log.topics.first().map(|t| format!("{:?}", t)))
+        .bind(log.block_number.map(|b| b.to_string()))
+        .bind(log.log_index.unwrap_or_default().to_string())
+        .bind(HexBytes32::from(log.transaction_hash.unwrap_or_default().0))
+        .bind(kind)
+        .bind(error)
+        .bind(Json(log))
+        .execute(&self.db)
+        .await?;
+    
+        Ok(())
+    }
+    
+    async fn save_progress(&self, contract: &str, block: i64) -> sqlx::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO indexing_progress (contract, indexed_block)
+            VALUES ($1, $2)
+            ON CONFLICT (contract) DO UPDATE
+            SET indexed_block = GREATEST(indexing_progress.indexed_block, EXCLUDED.indexed_block),
+                updated_at = NOW()
+            "#
+        )
+        .bind(contract)
+        .bind(block)
+        .execute(&self.db)
+        .await?;
+    
+        Ok(())
+    }
+    
+    async fn saved_progress(&self) -> sqlx::Result<Vec<(String, i64)>> {
+        sqlx::query_as("SELECT contract, indexed_block FROM indexing_progress")
+            .fetch_all(&self.db)
+            .await
+    }
+    
+    async fn counts(&self, now: i64) -> sqlx::Result<IndexedCounts> {
+        let counters: HashMap<String, i64> = sqlx::query_as::<_, (String, i64)>(
+            "SELECT name, value FROM stats_counters"
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .collect();
+    
+        let (submissions_24h, submissions_7d, active_devices_24h, active_devices_7d) =
+            sqlx::query_as::<_, (i64, i64, i64, i64)>(
+                r#"
+                SELECT
+                    COUNT(*) FILTER (WHERE timestamp >= $1),
+                    COUNT(*),
+                    COUNT(DISTINCT device_id_hash) FILTER (WHERE timestamp >= $1),
+                    COUNT(DISTINCT device_id_hash)
+                FROM data_submissions
+                WHERE timestamp >= $2
+                "#
+            )
+            .bind(now - super::DAY_SECS)
+            .bind(now - super::WEEK_SECS)
+            .fetch_one(&self.db)
+            .await?;
+    
+        Ok(IndexedCounts {
+            verifiers: counters.get("verifiers").copied().unwrap_or(0),
+            devices: counters.get("devices").copied().unwrap_or(0),
+            data_submissions: counters.get("data_submissions").copied().unwrap_or(0),
+            submissions_24h,
+            submissions_7d,
+            active_devices_24h,
+            active_devices_7d,
+        })
+    }
+    
+    async fn verifiers(
+        &self,
+        as_of_block: Option<i64>,
+        active: Option<bool>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<VerifierInfo>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            r#"
+            {VERIFIER_STATE_CTE}
+            SELECT COUNT(*) FROM verifiers
+            WHERE $2::BOOLEAN IS NULL OR (removed_at IS NULL) = $2
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(active)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, VerifierInfo>(&format!(
+            r#"
+            {VERIFIER_STATE_CTE}
+            SELECT address, registered_at, removed_at FROM verifiers
+            WHERE $2::BOOLEAN IS NULL OR (removed_at IS NULL) = $2
+            ORDER BY registered_at DESC, address
+            LIMIT $3 OFFSET $4
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(active)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn verifier_events(
+        &self,
+        address: HexAddress,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<VerifierEvent>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM verifier_events
+            WHERE verifier_address = $1 AND ($2::BIGINT IS NULL OR block_number <= $2)
+            "#
+        )
+        .bind(address)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, VerifierEvent>(
+            r#"
+            SELECT
+                id,
+                verifier_address,
+                event_type::text AS event_type,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM verifier_events
+            WHERE verifier_address = $1 AND ($2::BIGINT IS NULL OR block_number <= $2)
+            ORDER BY timestamp DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(address)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn devices(
+        &self,
+        as_of_block: Option<i64>,
+        filter: &DeviceFilter<'_>,
+        cutoffs: &StaleCutoffs,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceInfo>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            r#"
+            {DEVICE_STATE_CTE} {DEVICE_LIVENESS_CTE}
+            SELECT COUNT(*) FROM liveness
+            WHERE ($5::TEXT IS NULL OR status = $5)
+                AND ($6::TEXT IS NULL OR zone = $6)
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(&cutoffs.device_types)
+        .bind(&cutoffs.cutoffs)
+        .bind(cutoffs.default)
+        .bind(filter.status)
+        .bind(filter.zone)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceInfo>(&format!(
+            r#"
+            {DEVICE_STATE_CTE} {DEVICE_LIVENESS_CTE}
+            SELECT * FROM liveness
+            WHERE ($5::TEXT IS NULL OR status = $5)
+                AND ($6::TEXT IS NULL OR zone = $6)
+            ORDER BY registered_at DESC, device_id
+            LIMIT $7 OFFSET $8
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(&cutoffs.device_types)
+        .bind(&cutoffs.cutoffs)
+        .bind(cutoffs.default)
+        .bind(filter.status)
+        .bind(filter.zone)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn device(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        cutoffs: &StaleCutoffs,
+    ) -> sqlx::Result<Option<DeviceInfo>> {
+        sqlx::query_as::<_, DeviceInfo>(&format!(
+            "{DEVICE_STATE_CTE} {DEVICE_LIVENESS_CTE} SELECT * FROM liveness WHERE device_id = $5"
+        ))
+        .bind(as_of_block)
+        .bind(&cutoffs.device_types)
+        .bind(&cutoffs.cutoffs)
+        .bind(cutoffs.default)
+        .bind(device_id)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn device_events(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceEvent>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM device_events
+            WHERE device_id = $1 AND block_number <= COALESCE($2::BIGINT, 9223372036854775807)
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceEvent>(
+            r#"
+            SELECT
+                id,
+                device_id,
+                owner_address,
+                event_type::text AS event_type,
+                device_type,
+                zone,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM device_events
+            WHERE device_id = $1 AND block_number <= COALESCE($2::BIGINT, 9223372036854775807)
+            ORDER BY timestamp DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn device_owners(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceOwnership>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            "{DEVICE_CUSTODY_CTE} SELECT COUNT(*) FROM custody"
+        ))
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceOwnership>(&format!(
+            "{DEVICE_CUSTODY_CTE} SELECT * FROM custody ORDER BY position LIMIT $3 OFFSET $4"
+        ))
+        .bind(device_id)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn device_owner(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+    ) -> sqlx::Result<Option<DeviceOwnership>> {
+        sqlx::query_as::<_, DeviceOwnership>(&format!(
+            "{DEVICE_CUSTODY_CTE} SELECT * FROM custody ORDER BY position DESC LIMIT 1"
+        ))
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn device_transfers(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceTransfer>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM device_transfers
+            WHERE device_id = $1 AND ($2::BIGINT IS NULL OR block_number <= $2)
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceTransfer>(
+            r#"
+            SELECT
+                id,
+                device_id,
+                old_owner,
+                new_owner,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM device_transfers
+            WHERE device_id = $1 AND ($2::BIGINT IS NULL OR block_number <= $2)
+            ORDER BY block_number DESC, id DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn data_submissions(
+        &self,
+        device_id_hash: Option<HexBytes32>,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DataSubmission>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM data_submissions
+            WHERE ($1::BYTEA IS NULL OR device_id_hash = $1)
+                AND block_number <= COALESCE($2::BIGINT, 9223372036854775807)
+            "#
+        )
+        .bind(device_id_hash)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DataSubmission>(
+            r#"
+            SELECT
+                id,
+                data_hash,
+                device_id_hash,
+                device_owner,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM data_submissions
+            WHERE ($1::BYTEA IS NULL OR device_id_hash = $1)
+                AND block_number <= COALESCE($2::BIGINT, 9223372036854775807)
+            ORDER BY timestamp DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(device_id_hash)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn ownership_transfers(
+        &self,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<OwnershipTransfer>> {
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM ownership_transfers WHERE $1::BIGINT IS NULL OR block_number <= $1"
+        )
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, OwnershipTransfer>(
+            r#"
+            SELECT
+                id,
+                contract_type,
+                previous_owner,
+                new_owner,
+                block_number,
+                tx_hash,
+                created_at
+            FROM ownership_transfers
+            WHERE $1::BIGINT IS NULL OR block_number <= $1
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn marketplace_fees(&self, as_of_block: Option<i64>) -> sqlx::Result<Vec<MarketplaceConfig>> {
+        sqlx::query_as::<_, MarketplaceConfig>(&format!(
+            "{MARKETPLACE_FEES_CTE} SELECT * FROM fees ORDER BY block_number DESC, id DESC"
+        ))
+        .bind(as_of_block)
+        .fetch_all(&self.db)
+        .await
+    }
+    
+    async fn zone_stats(&self, since: i64) -> sqlx::Result<Vec<ZoneStats>> {
+        analytics::zone_stats(&self.db, since).await
+    }
+    
+    async fn device_type_stats(&self, since: i64) -> sqlx::Result<Vec<DeviceTypeStats>> {
+        analytics::device_type_stats(&self.db, since).await
+    }
+    
+    async fn archived_rows(&self, table: &str, as_of_block: Option<i64>) -> sqlx::Result<Option<ArchivedRows>> {
+        sqlx::query_as::<_, ArchivedRows>(
+            r#"
+            SELECT
+                table_name,
+                COUNT(*) AS archives,
+                SUM(row_count)::BIGINT AS row_count,
+                MAX(to_block) AS to_block,
+                MAX(to_timestamp) AS to_timestamp,
+                MAX(archived_at) AS last_archived_at
+            FROM archives
+            WHERE table_name = $1 AND from_block <= COALESCE($2::BIGINT, 9223372036854775807)
+            GROUP BY table_name
+            "#
+        )
+        .bind(table)
+        .bind(as_of_block)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn failed_events(
+        &self,
+        contract: Option<&str>,
+        kind: Option<FailureKind>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<FailedEvent>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM failed_events WHERE {FAILED_EVENT_FILTER}"
+        ))
+        .bind(contract)
+        .bind(kind)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, FailedEvent>(&format!(
+            r#"
+            SELECT {FAILED_EVENT_COLUMNS}
+            FROM failed_events
+            WHERE {FAILED_EVENT_FILTER}
+            ORDER BY id DESC
+            LIMIT $3 OFFSET $4
+            "#
+        ))
+        .bind(contract)
+        .bind(kind)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn failed_event(&self, id: i64) -> sqlx::Result<Option<FailedEvent>> {
+        sqlx::query_as::<_, FailedEvent>(&format!(
+            "SELECT {FAILED_EVENT_COLUMNS} FROM failed_events WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn record_failed_retry(&self, id: i64, error: &str) -> sqlx::Result<Option<FailedEvent>> {
+        sqlx::query_as::<_, FailedEvent>(&format!(
+            r#"
+            UPDATE failed_events
+            SET error = $2, attempts = attempts + 1, last_attempt_at = NOW()
+            WHERE id = $1
+            RETURNING {FAILED_EVENT_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .bind(error)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn discard_failed_event(&self, id: i64) -> sqlx::Result<bool> {
+        let deleted = sqlx::query("DELETE FROM failed_events WHERE id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+    
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_postgres;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn log_delivered_again_claims_its_unindexed_row() {
+        let storage = test_postgres().await;
+        // A device and transaction no other test uses
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&nanos.to_be_bytes());
+        let device_id = HexBytes32::from(bytes);
+        let tx_hash = HexBytes32::from(bytes);
+        let owner: HexAddress = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let block_number = 1_000;
+        
+        storage.prepare_block(block_number).await.unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO device_events (
+                device_id, owner_address, event_type, device_type, zone, timestamp, block_number, tx_hash
+            )
+            VALUES ($1, $2, 'registered', 1, 'test', 1700000000, $3, $4)
+            "#
+        )
+        .bind(device_id)
+        .bind(owner)
+        .bind(block_number)
+        .bind(tx_hash)
+        .execute(&storage.db)
+        .await
+        .unwrap();
+        
+        // The registration delivered again takes over the stored row, and is
+        // not streamed a second time
+        let device = NewDevice { device_id, owner, device_type: 1, zone: "test", timestamp: 1_700_000_000 };
+        let meta = LogMeta { block_number, tx_hash, log_index: 7 };
+        assert!(storage.register_device(&device, &meta).await.unwrap().is_none());
+        assert!(storage.register_device(&device, &meta).await.unwrap().is_none());
+        
+        let indexes: Vec<Option<i64>> = sqlx::query_scalar("SELECT log_index FROM device_events WHERE device_id = $1")
+            .bind(device_id)
+            .fetch_all(&storage.db)
+            .await
+            .unwrap();
+        assert_eq!(indexes, [Some(7)]);
+    }    
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn counters_move_only_when_the_answer_changes() {
+        let storage = test_postgres().await;
+        let owner: HexAddress = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let verifier: HexAddress = "0x2222222222222222222222222222222222222222".parse().unwrap();
+        let meta = |block_number, log_index| LogMeta { block_number, tx_hash: HexBytes32::from([9; 32]), log_index };
+        let counts = || async { storage.counts(0).await.map(|c| (c.devices, c.verifiers)).unwrap() };
+        
+        let device = |byte| NewDevice {
+            device_id: HexBytes32::from([byte; 32]),
+            owner,
+            device_type: 1,
+            zone: "test",
+            timestamp: 1,
+        };
+        storage.register_device(&device(1), &meta(1, 0)).await.unwrap();
+        assert_eq!(counts().await, (1, 0));
+        // A second registration of the same device does not count it again
+        storage.register_device(&device(1), &meta(1, 1)).await.unwrap();
+        assert_eq!(counts().await, (1, 0));
+        storage.register_device(&device(2), &meta(1, 2)).await.unwrap();
+        assert_eq!(counts().await, (2, 0));
+        
+        // Only a change between added and removed moves the verifier count
+        let steps = [
+            (true, 2, 1),
+            (true, 3, 1),
+            (false, 4, 0),
+            (false, 5, 0),
+            (true, 6, 1),
+            // Arriving late, a removal before the latest addition changes nothing
+            (false, 5, 1),
+        ];
+        for (log_index, (added, block_number, verifiers)) in steps.into_iter().enumerate() {
+            let meta = meta(block_number, log_index as i64);
+            if added {
+                storage.add_verifier(verifier, 1, &meta).await.unwrap();
+            } else {
+                storage.remove_verifier(verifier, 1, &meta).await.unwrap();
+            }
+            assert_eq!(counts().await, (2, verifiers), "after step {}", log_index);
+        }
+    }
+}
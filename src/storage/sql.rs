@@ -0,0 +1,108 @@
+//! PostgreSQL query fragments shared by the storage backend, the GraphQL
+//! schema and analytics
+//!
+//! Each constant opens a `WITH` clause that resolves state as of an optional
+//! block, for queries to select from.
+
+/// Chain of custody for device `$1` as of block `$2`: the registering owner
+/// followed by every transfer recipient, each with the block at which they
+/// handed it on. Holders follow the order of their logs; rows stored before
+/// log indexes were recorded come first in their block, registration first.
+pub(crate) const DEVICE_CUSTODY_CTE: &str = r#"
+    WITH holders AS (
+        SELECT device_id, owner_address, block_number, log_index, tx_hash, timestamp, 0 AS seq, id
+        FROM device_events
+        WHERE device_id = $1 AND event_type = 'registered'
+            AND block_number <= COALESCE($2::BIGINT, 9223372036854775807)
+        UNION ALL
+        SELECT device_id, new_owner, block_number, log_index, tx_hash, timestamp, 1 AS seq, id
+        FROM device_transfers
+        WHERE device_id = $1
+            AND ($2::BIGINT IS NULL OR block_number <= $2)
+    ),
+    custody AS (
+        SELECT
+            device_id,
+            owner_address,
+            block_number AS acquired_block,
+            tx_hash AS acquired_tx_hash,
+            timestamp AS acquired_at,
+            LEAD(block_number) OVER w AS released_block,
+            LEAD(tx_hash) OVER w AS released_tx_hash,
+            LEAD(timestamp) OVER w AS released_at,
+            ROW_NUMBER() OVER w AS position
+        FROM holders
+        WINDOW w AS (ORDER BY block_number, log_index NULLS FIRST, seq, id)
+    )
+"#;
+
+/// Registered devices as of block `$1`, with the owner in effect at that block
+pub(crate) const DEVICE_STATE_CTE: &str = r#"
+    WITH devices AS (
+        SELECT
+            r.device_id,
+            COALESCE(t.new_owner, r.owner_address) AS owner_address,
+            r.timestamp AS registered_at,
+            r.device_type,
+            r.zone
+        FROM (
+            SELECT DISTINCT ON (device_id)
+                device_id, owner_address, device_type, zone, timestamp
+            FROM device_events
+            WHERE event_type = 'registered'
+                AND block_number <= COALESCE($1::BIGINT, 9223372036854775807)
+            ORDER BY device_id, block_number, id
+        ) r
+        LEFT JOIN LATERAL (
+            SELECT new_owner
+            FROM device_transfers
+            WHERE device_id = r.device_id
+                AND ($1::BIGINT IS NULL OR block_number <= $1)
+            ORDER BY block_number DESC, log_index DESC NULLS LAST, id DESC
+            LIMIT 1
+        ) t ON TRUE
+    )
+"#;
+
+/// Verifiers seen as of block `$1`, with `removed_at` set when their latest
+/// event at that block was a removal
+pub(crate) const VERIFIER_STATE_CTE: &str = r#"
+    WITH events AS (
+        SELECT id, verifier_address, event_type, timestamp, block_number
+        FROM verifier_events
+        WHERE $1::BIGINT IS NULL OR block_number <= $1
+    ),
+    latest AS (
+        SELECT DISTINCT ON (verifier_address) verifier_address, event_type, timestamp
+        FROM events
+        ORDER BY verifier_address, block_number DESC, id DESC
+    ),
+    verifiers AS (
+        SELECT
+            l.verifier_address AS address,
+            COALESCE(
+                (SELECT MAX(e.timestamp) FROM events e
+                 WHERE e.verifier_address = l.verifier_address AND e.event_type = 'added'),
+                l.timestamp
+            ) AS registered_at,
+            CASE WHEN l.event_type = 'removed' THEN l.timestamp END AS removed_at
+        FROM latest l
+    )
+"#;
+
+/// Marketplace config updates as of block `$1`, each with the block at which
+/// the next update replaced it
+pub(crate) const MARKETPLACE_FEES_CTE: &str = r#"
+    WITH fees AS (
+        SELECT
+            id,
+            base_fee::TEXT AS base_fee,
+            block_number,
+            LEAD(block_number) OVER w AS effective_until_block,
+            tx_hash,
+            updated_at
+        FROM marketplace_config
+        WHERE $1::BIGINT IS NULL OR block_number <= $1
+        WINDOW w AS (ORDER BY block_number, id)
+    )
+"#;
@@ -0,0 +1,1229 @@
+//! SQLite storage
+//!
+//! Serves the same reads as PostgreSQL from the event tables alone:
+//! counters, device liveness and zone activity are computed per query
+//! instead of being kept up to date by triggers, which is fine for the
+//! event volumes a single-file database is meant for.
+
+use super::{
+    claim_unindexed, DeviceFilter, IndexedCounts, NewDevice, NewSubmission, Page, Paging, PoolSettings, Storage,
+};
+use crate::{api::device_id_hash, liveness::StaleCutoffs, models::*, LogMeta};
+use anyhow::Context;
+use async_trait::async_trait;
+use ethers::core::types::Log;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    types::Json,
+    Pool, Sqlite,
+};
+use std::str::FromStr;
+use tracing::info;
+
+/// Timestamp columns are RFC 3339 text, as the schema's defaults write them
+const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+/// Verifiers seen as of block `$1`, with `removed_at` set when their latest
+/// event at that block was a removal
+const VERIFIER_STATE_CTE: &str = r#"
+    WITH events AS (
+        SELECT id, verifier_address, event_type, timestamp, block_number
+        FROM verifier_events
+        WHERE $1 IS NULL OR block_number <= $1
+    ),
+    latest AS (
+        SELECT verifier_address, event_type, timestamp
+        FROM (
+            SELECT
+                verifier_address,
+                event_type,
+                timestamp,
+                ROW_NUMBER() OVER (
+                    PARTITION BY verifier_address ORDER BY block_number DESC, id DESC
+                ) AS n
+            FROM events
+        )
+        WHERE n = 1
+    ),
+    verifiers AS (
+        SELECT
+            l.verifier_address AS address,
+            COALESCE(
+                (SELECT MAX(e.timestamp) FROM events e
+                 WHERE e.verifier_address = l.verifier_address AND e.event_type = 'added'),
+                l.timestamp
+            ) AS registered_at,
+            CASE WHEN l.event_type = 'removed' THEN l.timestamp END AS removed_at
+        FROM latest l
+    )
+"#;
+
+/// Registered devices as of block `$1`, with the owner in effect at that block
+const DEVICE_STATE_CTE: &str = r#"
+    WITH registrations AS (
+        SELECT
+            device_id,
+            owner_address,
+            device_type,
+            zone,
+            timestamp,
+            ROW_NUMBER() OVER (PARTITION BY device_id ORDER BY block_number, id) AS n
+        FROM device_events
+        WHERE event_type = 'registered' AND ($1 IS NULL OR block_number <= $1)
+    ),
+    devices AS (
+        SELECT
+            r.device_id,
+            COALESCE(
+                (SELECT t.new_owner FROM device_transfers t
+                 WHERE t.device_id = r.device_id AND ($1 IS NULL OR t.block_number <= $1)
+                 ORDER BY t.block_number DESC, t.log_index DESC NULLS LAST, t.id DESC
+                 LIMIT 1),
+                r.owner_address
+            ) AS owner_address,
+            r.timestamp AS registered_at,
+            r.device_type,
+            r.zone
+        FROM registrations r
+        WHERE r.n = 1
+    )
+"#;
+
+/// Appended to `DEVICE_STATE_CTE`: each device with `last_submission_at` and
+/// `status`, where `$2` is a JSON object from device type to the oldest
+/// submission time still counted as active, and `$3` is that time for any
+/// other type
+const DEVICE_LIVENESS_CTE: &str = r#"
+    , latest_submissions AS (
+        SELECT h.device_id, MAX(s.timestamp) AS last_submission_at
+        FROM device_id_hashes h
+        JOIN data_submissions s ON s.device_id_hash = h.device_id_hash
+        GROUP BY h.device_id
+    ),
+    liveness AS (
+        SELECT
+            d.*,
+            l.last_submission_at,
+            CASE
+                WHEN l.last_submission_at IS NULL THEN 'never_reported'
+                WHEN l.last_submission_at
+                    >= COALESCE(json_extract($2, '$."' || d.device_type || '"'), $3) THEN 'active'
+                ELSE 'stale'
+            END AS status
+        FROM devices d
+        LEFT JOIN latest_submissions l ON l.device_id = d.device_id
+    )
+"#;
+
+/// Appended to `DEVICE_STATE_CTE`: submissions per device at or after `$2`
+const DEVICE_ACTIVITY_CTE: &str = r#"
+    , activity AS (
+        SELECT h.device_id, COUNT(*) AS submissions
+        FROM data_submissions s
+        JOIN device_id_hashes h ON h.device_id_hash = s.device_id_hash
+        WHERE s.timestamp >= $2
+        GROUP BY h.device_id
+    )
+"#;
+
+/// Chain of custody for device `$1` as of block `$2`, as in PostgreSQL
+const DEVICE_CUSTODY_CTE: &str = r#"
+    WITH holders AS (
+        SELECT device_id, owner_address, block_number, log_index, tx_hash, timestamp, 0 AS seq, id
+        FROM device_events
+        WHERE device_id = $1 AND event_type = 'registered'
+            AND ($2 IS NULL OR block_number <= $2)
+        UNION ALL
+        SELECT device_id, new_owner, block_number, log_index, tx_hash, timestamp, 1 AS seq, id
+        FROM device_transfers
+        WHERE device_id = $1
+            AND ($2 IS NULL OR block_number <= $2)
+    ),
+    custody AS (
+        SELECT
+            device_id,
+            owner_address,
+            block_number AS acquired_block,
+            tx_hash AS acquired_tx_hash,
+            timestamp AS acquired_at,
+            LEAD(block_number) OVER w AS released_block,
+            LEAD(tx_hash) OVER w AS released_tx_hash,
+            LEAD(timestamp) OVER w AS released_at,
+            ROW_NUMBER() OVER w AS position
+        FROM holders
+        WINDOW w AS (ORDER BY block_number, log_index NULLS FIRST, seq, id)
+    )
+"#;
+
+/// Marketplace config updates as of block `$1`, each with the block at which
+/// the next update replaced it
+const MARKETPLACE_FEES_CTE: &str = r#"
+    WITH fees AS (
+        SELECT
+            id,
+            base_fee,
+            block_number,
+            LEAD(block_number) OVER w AS effective_until_block,
+            tx_hash,
+            updated_at
+        FROM marketplace_config
+        WHERE $1 IS NULL OR block_number <= $1
+        WINDOW w AS (ORDER BY block_number, id)
+    )
+"#;
+
+const FAILED_EVENT_COLUMNS: &str = r#"
+    id, contract, topic, block_number, log_index,
+    tx_hash, kind, error, attempts, raw_log, last_attempt_at, created_at
+"#;
+
+/// Failed events of contract `$1` and kind `$2`, either unset for all
+const FAILED_EVENT_FILTER: &str = "($1 IS NULL OR contract = $1) AND ($2 IS NULL OR kind = $2)";
+
+pub struct SqliteStorage {
+    db: Pool<Sqlite>,
+}
+
+impl SqliteStorage {
+    /// Open the database file, creating it if needed, and run the
+    /// migrations in `migrations/sqlite/`
+    pub async fn connect(url: &str, settings: &PoolSettings) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)
+            .context("Invalid SQLite database URL")?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let db = settings
+            .pool_options()
+            .connect_with(options)
+            .await
+            .context("Failed to open database")?;
+    
+        info!("Opened SQLite database");
+    
+        sqlx::migrate!("./migrations/sqlite")
+            .run(&db)
+            .await
+            .context("Failed to run migrations")?;
+    
+        info!("Database migrations complete");
+    
+        Ok(Self { db })
+    }
+    
+    /// Open the database read-only, for the API
+    pub async fn connect_reads(url: &str, settings: &PoolSettings) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)
+            .context("Invalid SQLite database URL")?
+            .read_only(true);
+        let db = settings
+            .pool_options()
+            .connect_with(options)
+            .await
+            .context("Failed to open database for reads")?;
+    
+        Ok(Self { db })
+    }
+}
+
+/// `cutoffs` as the JSON object `DEVICE_LIVENESS_CTE` expects
+fn cutoffs_json(cutoffs: &StaleCutoffs) -> String {
+    let by_type: serde_json::Map<String, serde_json::Value> = cutoffs
+        .device_types
+        .iter()
+        .zip(&cutoffs.cutoffs)
+        .map(|(device_type, cutoff)| (device_type.to_string(), (*cutoff).into()))
+        .collect();
+    serde_json::Value::Object(by_type).to_string()
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn ping(&self) -> sqlx::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.db).await?;
+        Ok(())
+    }
+    
+    fn pool_usage(&self) -> (u32, usize) {
+        (self.db.size(), self.db.num_idle())
+    }
+    
+    async fn add_verifier(
+        &self,
+        verifier: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<Option<StreamEvent>> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("verifier_events", "verifier_address = $4 AND event_type = 'added'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(verifier)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            tx.commit().await?;
+            return Ok(None);
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO verifier_events (verifier_address, event_type, timestamp, block_number, tx_hash, log_index)
+            VALUES ($1, 'added', $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(verifier)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(None)
+    }
+    
+    async fn remove_verifier(&self, verifier: HexAddress, timestamp: i64, meta: &LogMeta) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("verifier_events", "verifier_address = $4 AND event_type = 'removed'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(verifier)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO verifier_events (verifier_address, event_type, timestamp, block_number, tx_hash, log_index)
+            VALUES ($1, 'removed', $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(verifier)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(())
+    }
+    
+    async fn transfer_ownership(
+        &self,
+        contract_type: &str,
+        previous_owner: HexAddress,
+        new_owner: HexAddress,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("ownership_transfers", "contract_type = $4 AND new_owner = $5"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(contract_type)
+            .bind(new_owner)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO ownership_transfers (
+                contract_type, previous_owner, new_owner, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(contract_type)
+        .bind(previous_owner)
+        .bind(new_owner)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(())
+    }
+    
+    async fn register_device(&self, device: &NewDevice<'_>, meta: &LogMeta) -> sqlx::Result<Option<StreamEvent>> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("device_events", "device_id = $4 AND event_type = 'registered'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(device.device_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            tx.commit().await?;
+            return Ok(None);
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO device_events (
+                device_id, owner_address, event_type, device_type, zone,
+                timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, 'registered', $3, $4, $5, $6, $7, $8)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(device.device_id)
+        .bind(device.owner)
+        .bind(device.device_type)
+        .bind(device.zone)
+        .bind(device.timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+    
+        sqlx::query("INSERT OR IGNORE INTO device_id_hashes (device_id_hash, device_id) VALUES ($1, $2)")
+            .bind(device_id_hash(&device.device_id))
+            .bind(device.device_id)
+            .execute(&mut *tx)
+            .await?;
+    
+        tx.commit().await?;
+    
+        Ok(None)
+    }
+    
+    async fn update_device(
+        &self,
+        device_id: HexBytes32,
+        owner: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("device_events", "device_id = $4 AND event_type = 'updated'"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(device_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO device_events (
+                device_id, owner_address, event_type, timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, 'updated', $3, $4, $5, $6)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(device_id)
+        .bind(owner)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(())
+    }
+    
+    async fn transfer_device(
+        &self,
+        device_id: HexBytes32,
+        old_owner: HexAddress,
+        new_owner: HexAddress,
+        timestamp: i64,
+        meta: &LogMeta,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("device_transfers", "device_id = $4 AND new_owner = $5"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(device_id)
+            .bind(new_owner)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO device_transfers (
+                device_id, old_owner, new_owner, timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(device_id)
+        .bind(old_owner)
+        .bind(new_owner)
+        .bind(timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(())
+    }
+    
+    async fn submit_data(&self, submission: &NewSubmission, meta: &LogMeta) -> sqlx::Result<Option<StreamEvent>> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("data_submissions", "data_hash = $4"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(submission.data_hash)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            tx.commit().await?;
+            return Ok(None);
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO data_submissions (
+                data_hash, device_id_hash, device_owner, timestamp, block_number, tx_hash, log_index
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(submission.data_hash)
+        .bind(submission.device_id_hash)
+        .bind(submission.owner)
+        .bind(submission.timestamp)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(None)
+    }
+    
+    async fn update_marketplace_config(&self, base_fee: &str, meta: &LogMeta) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let claimed = sqlx::query(&claim_unindexed("marketplace_config", "base_fee = $4"))
+            .bind(meta.block_number())
+            .bind(meta.tx_hash)
+            .bind(meta.log_index())
+            .bind(base_fee)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if claimed {
+            return tx.commit().await;
+        }
+    
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace_config (base_fee, block_number, tx_hash, log_index)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(base_fee)
+        .bind(meta.block_number())
+        .bind(meta.tx_hash)
+        .bind(meta.log_index())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        Ok(())
+    }
+    
+    async fn record_failed_log(
+        &self,
+        contract: &str,
+        log: &Log,
+        kind: FailureKind,
+        error: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO failed_events (
+                contract, topic, block_number, log_index, tx_hash, kind, error, raw_log
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (contract, tx_hash, log_index) DO UPDATE
+            SET kind = excluded.kind,
+                error = excluded.error,
+                attempts = failed_events.attempts + 1,
+                last_attempt_at = {NOW}
+            "#
+        ))
+        .bind(contract)
+        .bind(log.topics.first().map(|t| format!("{:?}", t)))
+        .bind(log.block_number.map(|b| b.to_string()))
+        .bind(log.log_index.unwrap_or_default().to_string())
+        .bind(HexBytes32::from(log.transaction_hash.unwrap_or_default().0))
+        .bind(kind)
+        .bind(error)
+        .bind(Json(log))
+        .execute(&self.db)
+        .await?;
+    
+        Ok(())
+    }
+    
+    async fn save_progress(&self, contract: &str, block: i64) -> sqlx::Result<()> {
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO indexing_progress (contract, indexed_block)
+            VALUES ($1, $2)
+            ON CONFLICT (contract) DO UPDATE
+            SET indexed_block = MAX(indexing_progress.indexed_block, excluded.indexed_block),
+                updated_at = {NOW}
+            "#
+        ))
+        .bind(contract)
+        .bind(block)
+        .execute(&self.db)
+        .await?;
+    
+        Ok(())
+    }
+    
+    async fn saved_progress(&self) -> sqlx::Result<Vec<(String, i64)>> {
+        sqlx::query_as("SELECT contract, indexed_block FROM indexing_progress")
+            .fetch_all(&self.db)
+            .await
+    }
+    
+    async fn counts(&self, now: i64) -> sqlx::Result<IndexedCounts> {
+        let (verifiers, devices, data_submissions) = sqlx::query_as::<_, (i64, i64, i64)>(&format!(
+            r#"
+            {VERIFIER_STATE_CTE}
+            SELECT
+                (SELECT COUNT(*) FROM verifiers WHERE removed_at IS NULL),
+                (SELECT COUNT(DISTINCT device_id) FROM device_events WHERE event_type = 'registered'),
+                (SELECT COUNT(*) FROM data_submissions)
+            "#
+        ))
+        .bind(None::<i64>)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let (submissions_24h, submissions_7d, active_devices_24h, active_devices_7d) =
+            sqlx::query_as::<_, (i64, i64, i64, i64)>(
+                r#"
+                SELECT
+                    COALESCE(SUM(timestamp >= $1), 0),
+                    COUNT(*),
+                    COUNT(DISTINCT CASE WHEN timestamp >= $1 THEN device_id_hash END),
+                    COUNT(DISTINCT device_id_hash)
+                FROM data_submissions
+                WHERE timestamp >= $2
+                "#
+            )
+            .bind(now - super::DAY_SECS)
+            .bind(now - super::WEEK_SECS)
+            .fetch_one(&self.db)
+            .await?;
+    
+        Ok(IndexedCounts {
+            verifiers,
+            devices,
+            data_submissions,
+            submissions_24h,
+            submissions_7d,
+            active_devices_24h,
+            active_devices_7d,
+        })
+    }
+    
+    async fn verifiers(
+        &self,
+        as_of_block: Option<i64>,
+        active: Option<bool>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<VerifierInfo>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            r#"
+            {VERIFIER_STATE_CTE}
+            SELECT COUNT(*) FROM verifiers
+            WHERE $2 IS NULL OR (removed_at IS NULL) = $2
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(active)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, VerifierInfo>(&format!(
+            r#"
+            {VERIFIER_STATE_CTE}
+            SELECT address, registered_at, removed_at FROM verifiers
+            WHERE $2 IS NULL OR (removed_at IS NULL) = $2
+            ORDER BY registered_at DESC, address
+            LIMIT $3 OFFSET $4
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(active)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn verifier_events(
+        &self,
+        address: HexAddress,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<VerifierEvent>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM verifier_events
+            WHERE verifier_address = $1 AND ($2 IS NULL OR block_number <= $2)
+            "#
+        )
+        .bind(address)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, VerifierEvent>(
+            r#"
+            SELECT id, verifier_address, event_type, timestamp, block_number, tx_hash, created_at
+            FROM verifier_events
+            WHERE verifier_address = $1 AND ($2 IS NULL OR block_number <= $2)
+            ORDER BY timestamp DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(address)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn devices(
+        &self,
+        as_of_block: Option<i64>,
+        filter: &DeviceFilter<'_>,
+        cutoffs: &StaleCutoffs,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceInfo>> {
+        let cutoffs_json = cutoffs_json(cutoffs);
+    
+        let total: i64 = sqlx::query_scalar(&format!(
+            r#"
+            {DEVICE_STATE_CTE} {DEVICE_LIVENESS_CTE}
+            SELECT COUNT(*) FROM liveness
+            WHERE ($4 IS NULL OR status = $4)
+                AND ($5 IS NULL OR zone = $5)
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(&cutoffs_json)
+        .bind(cutoffs.default)
+        .bind(filter.status)
+        .bind(filter.zone)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceInfo>(&format!(
+            r#"
+            {DEVICE_STATE_CTE} {DEVICE_LIVENESS_CTE}
+            SELECT * FROM liveness
+            WHERE ($4 IS NULL OR status = $4)
+                AND ($5 IS NULL OR zone = $5)
+            ORDER BY registered_at DESC, device_id
+            LIMIT $6 OFFSET $7
+            "#
+        ))
+        .bind(as_of_block)
+        .bind(&cutoffs_json)
+        .bind(cutoffs.default)
+        .bind(filter.status)
+        .bind(filter.zone)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn device(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        cutoffs: &StaleCutoffs,
+    ) -> sqlx::Result<Option<DeviceInfo>> {
+        sqlx::query_as::<_, DeviceInfo>(&format!(
+            "{DEVICE_STATE_CTE} {DEVICE_LIVENESS_CTE} SELECT * FROM liveness WHERE device_id = $4"
+        ))
+        .bind(as_of_block)
+        .bind(cutoffs_json(cutoffs))
+        .bind(cutoffs.default)
+        .bind(device_id)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn device_events(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceEvent>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM device_events
+            WHERE device_id = $1 AND ($2 IS NULL OR block_number <= $2)
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceEvent>(
+            r#"
+            SELECT
+                id,
+                device_id,
+                owner_address,
+                event_type,
+                device_type,
+                zone,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM device_events
+            WHERE device_id = $1 AND ($2 IS NULL OR block_number <= $2)
+            ORDER BY timestamp DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn device_owners(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceOwnership>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            "{DEVICE_CUSTODY_CTE} SELECT COUNT(*) FROM custody"
+        ))
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceOwnership>(&format!(
+            "{DEVICE_CUSTODY_CTE} SELECT * FROM custody ORDER BY position LIMIT $3 OFFSET $4"
+        ))
+        .bind(device_id)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn device_owner(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+    ) -> sqlx::Result<Option<DeviceOwnership>> {
+        sqlx::query_as::<_, DeviceOwnership>(&format!(
+            "{DEVICE_CUSTODY_CTE} SELECT * FROM custody ORDER BY position DESC LIMIT 1"
+        ))
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn device_transfers(
+        &self,
+        device_id: HexBytes32,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DeviceTransfer>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM device_transfers
+            WHERE device_id = $1 AND ($2 IS NULL OR block_number <= $2)
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DeviceTransfer>(
+            r#"
+            SELECT
+                id,
+                device_id,
+                old_owner,
+                new_owner,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM device_transfers
+            WHERE device_id = $1 AND ($2 IS NULL OR block_number <= $2)
+            ORDER BY block_number DESC, id DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(device_id)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn data_submissions(
+        &self,
+        device_id_hash: Option<HexBytes32>,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<DataSubmission>> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM data_submissions
+            WHERE ($1 IS NULL OR device_id_hash = $1)
+                AND ($2 IS NULL OR block_number <= $2)
+            "#
+        )
+        .bind(device_id_hash)
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, DataSubmission>(
+            r#"
+            SELECT
+                id,
+                data_hash,
+                device_id_hash,
+                device_owner,
+                timestamp,
+                block_number,
+                tx_hash,
+                created_at
+            FROM data_submissions
+            WHERE ($1 IS NULL OR device_id_hash = $1)
+                AND ($2 IS NULL OR block_number <= $2)
+            ORDER BY timestamp DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(device_id_hash)
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn ownership_transfers(
+        &self,
+        as_of_block: Option<i64>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<OwnershipTransfer>> {
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM ownership_transfers WHERE $1 IS NULL OR block_number <= $1"
+        )
+        .bind(as_of_block)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, OwnershipTransfer>(
+            r#"
+            SELECT
+                id,
+                contract_type,
+                previous_owner,
+                new_owner,
+                block_number,
+                tx_hash,
+                created_at
+            FROM ownership_transfers
+            WHERE $1 IS NULL OR block_number <= $1
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(as_of_block)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn marketplace_fees(&self, as_of_block: Option<i64>) -> sqlx::Result<Vec<MarketplaceConfig>> {
+        sqlx::query_as::<_, MarketplaceConfig>(&format!(
+            "{MARKETPLACE_FEES_CTE} SELECT * FROM fees ORDER BY block_number DESC, id DESC"
+        ))
+        .bind(as_of_block)
+        .fetch_all(&self.db)
+        .await
+    }
+    
+    async fn zone_stats(&self, since: i64) -> sqlx::Result<Vec<ZoneStats>> {
+        sqlx::query_as::<_, ZoneStats>(&format!(
+            r#"
+            {DEVICE_STATE_CTE} {DEVICE_ACTIVITY_CTE}
+            SELECT
+                d.zone,
+                COUNT(*) AS device_count,
+                COUNT(a.device_id) AS active_devices,
+                COALESCE(SUM(a.submissions), 0) AS submissions
+            FROM devices d
+            LEFT JOIN activity a ON a.device_id = d.device_id
+            WHERE d.zone IS NOT NULL
+            GROUP BY d.zone
+            ORDER BY d.zone
+            "#
+        ))
+        .bind(None::<i64>)
+        .bind(since)
+        .fetch_all(&self.db)
+        .await
+    }
+    
+    async fn device_type_stats(&self, since: i64) -> sqlx::Result<Vec<DeviceTypeStats>> {
+        sqlx::query_as::<_, DeviceTypeStats>(&format!(
+            r#"
+            {DEVICE_STATE_CTE} {DEVICE_ACTIVITY_CTE}
+            SELECT
+                d.device_type,
+                COUNT(*) AS device_count,
+                COUNT(a.device_id) AS active_devices,
+                COALESCE(SUM(a.submissions), 0) AS submissions
+            FROM devices d
+            LEFT JOIN activity a ON a.device_id = d.device_id
+            WHERE d.device_type IS NOT NULL
+            GROUP BY d.device_type
+            ORDER BY d.device_type
+            "#
+        ))
+        .bind(None::<i64>)
+        .bind(since)
+        .fetch_all(&self.db)
+        .await
+    }
+    
+    async fn failed_events(
+        &self,
+        contract: Option<&str>,
+        kind: Option<FailureKind>,
+        paging: Paging,
+    ) -> sqlx::Result<Page<FailedEvent>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM failed_events WHERE {FAILED_EVENT_FILTER}"
+        ))
+        .bind(contract)
+        .bind(kind)
+        .fetch_one(&self.db)
+        .await?;
+    
+        let rows = sqlx::query_as::<_, FailedEvent>(&format!(
+            r#"
+            SELECT {FAILED_EVENT_COLUMNS}
+            FROM failed_events
+            WHERE {FAILED_EVENT_FILTER}
+            ORDER BY id DESC
+            LIMIT $3 OFFSET $4
+            "#
+        ))
+        .bind(contract)
+        .bind(kind)
+        .bind(paging.limit)
+        .bind(paging.offset)
+        .fetch_all(&self.db)
+        .await?;
+    
+        Ok(Page { rows, total })
+    }
+    
+    async fn failed_event(&self, id: i64) -> sqlx::Result<Option<FailedEvent>> {
+        sqlx::query_as::<_, FailedEvent>(&format!(
+            "SELECT {FAILED_EVENT_COLUMNS} FROM failed_events WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn record_failed_retry(&self, id: i64, error: &str) -> sqlx::Result<Option<FailedEvent>> {
+        sqlx::query_as::<_, FailedEvent>(&format!(
+            r#"
+            UPDATE failed_events
+            SET error = $2, attempts = attempts + 1, last_attempt_at = {NOW}
+            WHERE id = $1
+            RETURNING {FAILED_EVENT_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .bind(error)
+        .fetch_optional(&self.db)
+        .await
+    }
+    
+    async fn discard_failed_event(&self, id: i64) -> sqlx::Result<bool> {
+        let deleted = sqlx::query("DELETE FROM failed_events WHERE id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+    
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[tokio::test]
+    async fn log_delivered_again_claims_its_unindexed_row() {
+        let storage = SqliteStorage::connect("sqlite::memory:", &PoolSettings::for_tests()).await.unwrap();
+        let device = HexBytes32::from([1; 32]);
+        let old_owner: HexAddress = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let new_owner: HexAddress = "0x2222222222222222222222222222222222222222".parse().unwrap();
+        let tx_hash = HexBytes32::from([9; 32]);
+        
+        // Two identical transfers in one transaction, stored before log
+        // indexes were recorded
+        for _ in 0..2 {
+            sqlx::query(
+                r#"
+                INSERT INTO device_transfers (device_id, old_owner, new_owner, timestamp, block_number, tx_hash)
+                VALUES ($1, $2, $3, 1700000000, 100, $4)
+                "#
+            )
+            .bind(device)
+            .bind(old_owner)
+            .bind(new_owner)
+            .bind(tx_hash)
+            .execute(&storage.db)
+            .await
+            .unwrap();
+        }
+        
+        // Delivered again, each log claims one of them, then is stored once
+        for log_index in [3, 4, 3, 4] {
+            let meta = LogMeta { block_number: 100, tx_hash, log_index };
+            storage.transfer_device(device, old_owner, new_owner, 1_700_000_000, &meta).await.unwrap();
+        }
+        
+        let indexes: Vec<Option<i64>> = sqlx::query_scalar("SELECT log_index FROM device_transfers ORDER BY id")
+            .fetch_all(&storage.db)
+            .await
+            .unwrap();
+        assert_eq!(indexes, [Some(3), Some(4)]);
+        
+        // A log from another transaction is not mistaken for them
+        let meta = LogMeta { block_number: 100, tx_hash: HexBytes32::from([8; 32]), log_index: 3 };
+        storage.transfer_device(device, old_owner, new_owner, 1_700_000_000, &meta).await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM device_transfers")
+            .fetch_one(&storage.db)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+    }    
+    #[tokio::test]
+    async fn custody_follows_log_order() {
+        let storage = SqliteStorage::connect("sqlite::memory:", &PoolSettings::for_tests()).await.unwrap();
+        let device_id = HexBytes32::from([1; 32]);
+        let owner = |digit: &str| digit.repeat(40).parse::<HexAddress>().unwrap();
+        let meta = |block_number, log_index| LogMeta { block_number, tx_hash: HexBytes32::from([9; 32]), log_index };
+        let device = NewDevice { device_id, owner: owner("1"), device_type: 1, zone: "test", timestamp: 1 };
+        
+        storage.register_device(&device, &meta(10, 0)).await.unwrap();
+        // Two transfers in one block, stored out of log order
+        storage.transfer_device(device_id, owner("2"), owner("3"), 2, &meta(20, 5)).await.unwrap();
+        storage.transfer_device(device_id, owner("1"), owner("2"), 2, &meta(20, 4)).await.unwrap();
+        
+        let paging = Paging { limit: 10, offset: 0 };
+        let holders: Vec<HexAddress> = storage
+            .device_owners(device_id, None, paging)
+            .await
+            .unwrap()
+            .rows
+            .into_iter()
+            .map(|ownership| ownership.owner_address)
+            .collect();
+        assert_eq!(holders, [owner("1"), owner("2"), owner("3")]);
+        
+        let cutoffs = StaleCutoffs { device_types: Vec::new(), cutoffs: Vec::new(), default: 0 };
+        let current = storage.device(device_id, None, &cutoffs).await.unwrap().unwrap();
+        assert_eq!(current.owner_address, owner("3"));
+    }
+}
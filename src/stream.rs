@@ -0,0 +1,375 @@
+//! Live event stream shared between the indexer and `/stream/events`
+//!
+//! Handlers append each streamed event to `event_stream` in the same
+//! transaction as the event row, holding an advisory lock so ids are
+//! assigned in commit order. Subscribers then only need a cursor: every
+//! event with a smaller id is already committed, so reading `id > cursor`
+//! after each wake-up never skips an event.
+
+use crate::{
+    models::{HexAddress, HexBytes32, StreamEvent},
+    webhooks,
+};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+/// Event types published on the stream
+pub const VERIFIER_ADDED: &str = "VerifierAdded";
+pub const DEVICE_REGISTERED: &str = "DeviceRegistered";
+pub const DATA_SUBMITTED: &str = "DataSubmitted";
+
+pub const EVENT_TYPES: [&str; 3] = [VERIFIER_ADDED, DEVICE_REGISTERED, DATA_SUBMITTED];
+
+/// Advisory lock key serialising appends to `event_stream`
+const STREAM_LOCK: i64 = 0x6576_656e_7473;
+
+/// Events buffered per subscriber before it is considered lagging
+const BUS_CAPACITY: usize = 1024;
+
+/// Events read from the database per catch-up query
+const BACKFILL_BATCH: i64 = 500;
+
+/// Payload fields holding an address
+const ADDRESS_FIELDS: [&str; 3] = ["verifier_address", "owner_address", "device_owner"];
+
+/// An event about to be appended to the stream
+pub struct NewStreamEvent<'a> {
+    pub contract: &'a str,
+    pub event_type: &'a str,
+    pub owner: Option<HexAddress>,
+    pub device: Option<HexBytes32>,
+    pub block_number: i64,
+    pub tx_hash: HexBytes32,
+    pub payload: serde_json::Value,
+}
+
+/// Take the stream lock for the rest of `tx`
+///
+/// Must be called before inserting the event row, so events reach the
+/// stream in the order their transactions commit.
+pub async fn lock(tx: &mut Transaction<'_, Postgres>) -> sqlx::Result<()> {
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(STREAM_LOCK)
+        .execute(&mut **tx)
+        .await?;
+    
+    Ok(())
+}
+
+/// Append an event to the stream within `tx`
+async fn append(
+    tx: &mut Transaction<'_, Postgres>,
+    event: NewStreamEvent<'_>,
+) -> sqlx::Result<StreamEvent> {
+    sqlx::query_as::<_, StreamEvent>(
+        r#"
+        INSERT INTO event_stream (
+            contract, event_type, owner, device, block_number, tx_hash, payload
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *
+        "#
+    )
+    .bind(event.contract)
+    .bind(event.event_type)
+    .bind(event.owner)
+    .bind(event.device)
+    .bind(event.block_number)
+    .bind(event.tx_hash)
+    .bind(checksum_addresses(event.payload))
+    .fetch_one(&mut **tx)
+    .await
+}
+
+/// `payload` with its addresses in EIP-55 checksummed form, as the REST API
+/// serves them; the database can only write them as lowercase hex
+fn checksum_addresses(mut payload: serde_json::Value) -> serde_json::Value {
+    for field in ADDRESS_FIELDS {
+        let Some(value) = payload.get_mut(field) else {
+            continue;
+        };
+        if let Some(address) = value.as_str().and_then(|s| s.parse::<HexAddress>().ok()) {
+            *value = address.to_string().into();
+        }
+    }
+    
+    payload
+}
+
+/// Append `event` (if any) to the stream and queue its webhook deliveries,
+/// then commit `tx`, returning the appended event for the caller to announce
+/// on the [`EventBus`]
+pub async fn commit(
+    mut tx: Transaction<'_, Postgres>,
+    event: Option<NewStreamEvent<'_>>,
+) -> sqlx::Result<Option<StreamEvent>> {
+    let streamed = match event {
+        Some(event) => {
+            let streamed = append(&mut tx, event).await?;
+            webhooks::enqueue(&mut tx, &streamed).await?;
+            Some(streamed)
+        }
+        None => None,
+    };
+    
+    tx.commit().await?;
+    
+    Ok(streamed)
+}
+
+/// Wakes stream subscribers when the indexer commits an event
+pub struct EventBus {
+    sender: broadcast::Sender<StreamEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        Self { sender }
+    }
+    
+    /// Announce a committed event
+    pub fn publish(&self, event: StreamEvent) {
+        // No receivers just means nobody is streaming
+        let _ = self.sender.send(event);
+    }
+    
+    /// Announce the event a storage write appended, if it appended one
+    pub fn announce(&self, event: Option<StreamEvent>) {
+        if let Some(event) = event {
+            self.publish(event);
+        }
+    }
+    
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Filters accepted by `/stream/events`; unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamFilter {
+    pub contract: Option<String>,
+    pub event_type: Option<String>,
+    pub owner: Option<HexAddress>,
+    pub device: Option<HexBytes32>,
+}
+
+impl StreamFilter {
+    fn matches(&self, event: &StreamEvent) -> bool {
+        fn field<T: PartialEq + ?Sized>(filter: Option<&T>, value: Option<&T>) -> bool {
+            filter.is_none_or(|f| Some(f) == value)
+        }
+    
+        field(self.contract.as_deref(), Some(event.contract.as_str()))
+            && field(self.event_type.as_deref(), Some(event.event_type.as_str()))
+            && field(self.owner.as_ref(), event.owner.as_ref())
+            && field(self.device.as_ref(), event.device.as_ref())
+    }
+}
+
+/// Follow the stream from just after `cursor`, or from now when `None`
+///
+/// Matching events are delivered in id order on the returned channel until
+/// the receiver is dropped or the database becomes unavailable.
+pub fn follow(
+    db: Pool<Postgres>,
+    bus: &EventBus,
+    filter: StreamFilter,
+    cursor: Option<i64>,
+) -> mpsc::Receiver<StreamEvent> {
+    let (sender, receiver) = mpsc::channel(BACKFILL_BATCH as usize);
+    // Subscribe before reading the database so nothing committed in between
+    // is missed
+    let mut live = bus.subscribe();
+    
+    tokio::spawn(async move {
+        let mut last = match cursor {
+            Some(cursor) => cursor,
+            None => match latest_id(&db).await {
+                Ok(id) => id,
+                Err(e) => {
+                    warn!("Event stream failed to start: {}", e);
+                    return;
+                }
+            },
+        };
+        
+        // Catch up on events committed after the cursor
+        if let Err(e) = catch_up(&db, &filter, &mut last, None, &sender).await {
+            warn!("Event stream backfill failed: {}", e);
+            return;
+        }
+        
+        loop {
+            let event = match live.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    // Missed announcements are still in the table
+                    if let Err(e) = catch_up(&db, &filter, &mut last, None, &sender).await {
+                        warn!("Event stream catch-up failed: {}", e);
+                        return;
+                    }
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            
+            if event.id <= last {
+                continue;
+            }
+            
+            if event.id > last + 1 {
+                // Announcements can arrive out of commit order; anything
+                // before this event is committed, so read it from the table
+                if let Err(e) = catch_up(&db, &filter, &mut last, Some(event.id - 1), &sender).await {
+                    warn!("Event stream catch-up failed: {}", e);
+                    return;
+                }
+            }
+            
+            last = event.id;
+            if filter.matches(&event) && sender.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+    
+    receiver
+}
+
+async fn latest_id(db: &Pool<Postgres>) -> sqlx::Result<i64> {
+    sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM event_stream")
+        .fetch_one(db)
+        .await
+}
+
+/// Send every matching event after `last` (up to `until`, if given),
+/// advancing `last` past everything read
+async fn catch_up(
+    db: &Pool<Postgres>,
+    filter: &StreamFilter,
+    last: &mut i64,
+    until: Option<i64>,
+    sender: &mpsc::Sender<StreamEvent>,
+) -> sqlx::Result<()> {
+    loop {
+        let events = sqlx::query_as::<_, StreamEvent>(
+            r#"
+            SELECT * FROM event_stream
+            WHERE id > $1
+              AND ($2::BIGINT IS NULL OR id <= $2)
+              AND ($3::TEXT IS NULL OR contract = $3)
+              AND ($4::TEXT IS NULL OR event_type = $4)
+              AND ($5::BYTEA IS NULL OR owner = $5)
+              AND ($6::BYTEA IS NULL OR device = $6)
+            ORDER BY id
+            LIMIT $7
+            "#
+        )
+        .bind(*last)
+        .bind(until)
+        .bind(&filter.contract)
+        .bind(&filter.event_type)
+        .bind(filter.owner)
+        .bind(filter.device)
+        .bind(BACKFILL_BATCH)
+        .fetch_all(db)
+        .await?;
+        
+        let done = (events.len() as i64) < BACKFILL_BATCH;
+        for event in events {
+            *last = event.id;
+            if sender.send(event).await.is_err() {
+                return Ok(());
+            }
+        }
+        
+        if done {
+            if let Some(until) = until {
+                *last = (*last).max(until);
+            }
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_postgres;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    
+    /// Commit a stream event for `contract` and announce it on `bus`
+    async fn emit(db: &Pool<Postgres>, bus: &EventBus, contract: &str, block_number: i64) -> StreamEvent {
+        let mut tx = db.begin().await.unwrap();
+        lock(&mut tx).await.unwrap();
+        let event = NewStreamEvent {
+            contract,
+            event_type: DATA_SUBMITTED,
+            owner: None,
+            device: None,
+            block_number,
+            tx_hash: HexBytes32::from([0xab; 32]),
+            payload: serde_json::json!({}),
+        };
+        let streamed = commit(tx, Some(event)).await.unwrap().unwrap();
+        bus.publish(streamed.clone());
+        streamed
+    }
+    
+    async fn next(events: &mut mpsc::Receiver<StreamEvent>) -> StreamEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("no event within 5s")
+            .expect("stream ended")
+    }
+    
+    #[test]
+    fn payload_addresses_are_checksummed() {
+        let payload = serde_json::json!({
+            "owner_address": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+            "device_id": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed5aaeb6053f3e94c9b9a09f33",
+            "id": 1,
+        });
+        assert_eq!(
+            checksum_addresses(payload),
+            serde_json::json!({
+                "owner_address": "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+                "device_id": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed5aaeb6053f3e94c9b9a09f33",
+                "id": 1,
+            }),
+        );
+    }
+    
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn follow_resumes_after_cursor() {
+        let storage = test_postgres().await;
+        let db = storage.pool();
+        let bus = EventBus::new();
+        // Keeps this test's events apart from anything else in the database
+        let contract = format!("test-{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos());
+        let filter = StreamFilter { contract: Some(contract.clone()), ..Default::default() };
+        
+        let first = emit(db, &bus, &contract, 1).await;
+        let second = emit(db, &bus, &contract, 2).await;
+        let third = emit(db, &bus, &contract, 3).await;
+        
+        // Reconnecting with the first event's id replays the rest, in order,
+        // then follows live events
+        let mut events = follow(db.clone(), &bus, filter, Some(first.id));
+        assert_eq!(next(&mut events).await.id, second.id);
+        assert_eq!(next(&mut events).await.id, third.id);
+        
+        let fourth = emit(db, &bus, &contract, 4).await;
+        assert_eq!(next(&mut events).await.id, fourth.id);
+        
+        // Announcing an event again does not deliver it twice
+        bus.publish(third.clone());
+        let fifth = emit(db, &bus, &contract, 5).await;
+        assert_eq!(next(&mut events).await.id, fifth.id);
+    }
+}
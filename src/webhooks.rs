@@ -0,0 +1,516 @@
+//! Outbound webhook delivery
+//!
+//! Deliveries are queued in `webhook_deliveries` in the same transaction
+//! that indexes the event, then POSTed by a background dispatcher. A
+//! delivery is only marked delivered after the endpoint answers 2xx, so
+//! every matching event is delivered at least once; failures are retried
+//! with exponential backoff until they are moved to the dead-letter state.
+//!
+//! Webhooks are only delivered to public addresses: URLs naming or
+//! resolving to loopback, private, link-local and similar addresses are
+//! refused when registered and again when each delivery connects, and
+//! redirects are not followed.
+
+use crate::{models::StreamEvent, AppState};
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect, Url,
+};
+use sha2::Sha256;
+use sqlx::{FromRow, Pool, Postgres, Transaction};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+/// Header carrying `sha256=<hex HMAC of "{timestamp}.{body}">`
+pub const SIGNATURE_HEADER: &str = "X-Lcore-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Lcore-Timestamp";
+pub const EVENT_HEADER: &str = "X-Lcore-Event";
+pub const DELIVERY_HEADER: &str = "X-Lcore-Delivery";
+
+/// Attempts made before a delivery is dead-lettered
+const MAX_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry, doubled on each further attempt
+const RETRY_BASE: Duration = Duration::from_secs(10);
+
+/// Longest delay between two attempts
+const RETRY_MAX: Duration = Duration::from_secs(60 * 60);
+
+/// Time allowed for the endpoint to answer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a claimed delivery is hidden from other dispatchers; a process
+/// that dies mid-delivery has it retried once this expires
+const CLAIM_LEASE_SECS: f64 = 60.0;
+
+/// Deliveries claimed per round
+const DISPATCH_BATCH: i64 = 50;
+
+/// Fallback polling interval for retries that become due
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Queue a delivery of `event` to every webhook subscribed to its type
+pub async fn enqueue(tx: &mut Transaction<'_, Postgres>, event: &StreamEvent) -> sqlx::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO webhook_deliveries (webhook_id, event_id)
+        SELECT id, $1 FROM webhooks
+        WHERE event_types = '{}' OR $2 = ANY(event_types)
+        ON CONFLICT DO NOTHING
+        "#
+    )
+    .bind(event.id)
+    .bind(&event.event_type)
+    .execute(&mut **tx)
+    .await?;
+    
+    Ok(())
+}
+
+#[derive(FromRow)]
+struct DueDelivery {
+    delivery_id: i64,
+    attempts: i32,
+    url: String,
+    secret: String,
+    #[sqlx(flatten)]
+    event: StreamEvent,
+}
+
+/// Check `url` is one webhooks may be delivered to: http or https, on a
+/// host that is, or only resolves to, public addresses
+pub async fn check_url(url: &Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("Webhook URL must be http or https".to_string());
+    }
+    
+    match (host_ip(url), url.host_str()) {
+        (Some(ip), _) => check_ip(ip),
+        (None, Some(host)) => resolve_public(host).await.map(drop),
+        (None, None) => Err("Webhook URL has no host".to_string()),
+    }
+}
+
+/// The address a URL names directly rather than by host name
+fn host_ip(url: &Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+fn check_ip(ip: IpAddr) -> Result<(), String> {
+    if is_internal(ip) {
+        return Err(format!("Webhook URL points at internal address {}", ip));
+    }
+    Ok(())
+}
+
+/// The addresses `host` resolves to, refusing it when any is internal
+async fn resolve_public(host: &str) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} does not resolve to any address", host));
+    }
+    
+    for addr in &addrs {
+        if is_internal(addr.ip()) {
+            return Err(format!("{} resolves to internal address {}", host, addr.ip()));
+        }
+    }
+    
+    Ok(addrs)
+}
+
+/// Whether `ip` belongs to the host or a private network rather than the
+/// public internet
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_internal_v4(v4);
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // Shared address space 100.64.0.0/10
+        || (a == 100 && (b & 0xc0) == 64)
+}
+
+/// Resolver for deliveries, so a host cannot be pointed at an internal
+/// address after its webhook was registered
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// HTTP client for deliveries: public addresses only, no redirects
+fn delivery_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+}
+
+/// Deliver queued webhooks until the process exits
+pub async fn run_dispatcher(state: Arc<AppState>, db: Pool<Postgres>) {
+    let client = match delivery_client() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Webhook dispatcher failed to start: {}", e);
+            return;
+        }
+    };
+    let mut wake = state.events.subscribe();
+    
+    loop {
+        match dispatch_due(&db, &client).await {
+            // A full batch suggests more are already due
+            Ok(claimed) if claimed == DISPATCH_BATCH as usize => continue,
+            Ok(_) => {}
+            Err(e) => warn!("Webhook dispatch failed: {}", e),
+        }
+    
+        tokio::select! {
+            _ = wake.recv() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Claim the deliveries that are due and attempt each one
+async fn dispatch_due(db: &Pool<Postgres>, client: &reqwest::Client) -> sqlx::Result<usize> {
+    let due = sqlx::query_as::<_, DueDelivery>(
+        r#"
+        UPDATE webhook_deliveries d
+        SET next_attempt_at = NOW() + make_interval(secs => $2)
+        FROM (
+            SELECT id FROM webhook_deliveries
+            WHERE status = 'pending' AND next_attempt_at <= NOW()
+            ORDER BY next_attempt_at, id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        ) claimed, webhooks w, event_stream e
+        WHERE d.id = claimed.id AND w.id = d.webhook_id AND e.id = d.event_id
+        RETURNING d.id AS delivery_id, d.attempts, w.url, w.secret, e.*
+        "#
+    )
+    .bind(DISPATCH_BATCH)
+    .bind(CLAIM_LEASE_SECS)
+    .fetch_all(db)
+    .await?;
+    let claimed = due.len();
+    
+    let mut attempts = JoinSet::new();
+    for delivery in due {
+        let db = db.clone();
+        let client = client.clone();
+        attempts.spawn(async move {
+            // Host names are checked as they resolve, addresses here
+            let address = Url::parse(&delivery.url).ok().and_then(|url| host_ip(&url));
+            let outcome = match address.map(check_ip) {
+                Some(Err(e)) => (None, Some(e)),
+                _ => attempt(&client, &delivery).await,
+            };
+            if let Err(e) = record_outcome(&db, &delivery, outcome).await {
+                warn!("Failed to record webhook delivery {}: {}", delivery.delivery_id, e);
+            }
+        });
+    }
+    while attempts.join_next().await.is_some() {}
+    
+    Ok(claimed)
+}
+
+/// Outcome of one POST: the response status, if any, and an error for
+/// anything other than a 2xx answer
+type Outcome = (Option<u16>, Option<String>);
+
+async fn attempt(client: &reqwest::Client, delivery: &DueDelivery) -> Outcome {
+    let body = match serde_json::to_vec(&delivery.event) {
+        Ok(body) => body,
+        Err(e) => return (None, Some(e.to_string())),
+    };
+    let timestamp = chrono::Utc::now().timestamp();
+    
+    let response = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, timestamp, &body))
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(EVENT_HEADER, &delivery.event.event_type)
+        .header(DELIVERY_HEADER, delivery.delivery_id)
+        .body(body)
+        .send()
+        .await;
+    
+    match response {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+        Ok(response) => (
+            Some(response.status().as_u16()),
+            Some(format!("Endpoint answered {}", response.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+/// Sign `body` as sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// What becomes of a delivery after an attempt
+#[derive(Debug, PartialEq)]
+enum Settlement {
+    Delivered,
+    /// Attempted again once the delay has passed
+    Retry(Duration),
+    Dead,
+}
+
+/// Settle a delivery whose `attempts`th attempt failed with `error`, or
+/// succeeded without one
+fn settle(attempts: i32, error: Option<&str>) -> Settlement {
+    match error {
+        None => Settlement::Delivered,
+        Some(_) if attempts >= MAX_ATTEMPTS => Settlement::Dead,
+        Some(_) => Settlement::Retry(retry_delay(attempts)),
+    }
+}
+
+async fn record_outcome(
+    db: &Pool<Postgres>,
+    delivery: &DueDelivery,
+    (status_code, error): Outcome,
+) -> sqlx::Result<()> {
+    let attempts = delivery.attempts + 1;
+    let status_code = status_code.map(i32::from);
+    
+    let (status, delay) = match settle(attempts, error.as_deref()) {
+        Settlement::Delivered => {
+            sqlx::query(
+                r#"
+                UPDATE webhook_deliveries
+                SET status = 'delivered', attempts = $2, last_status_code = $3,
+                    last_error = NULL, delivered_at = NOW()
+                WHERE id = $1
+                "#
+            )
+            .bind(delivery.delivery_id)
+            .bind(attempts)
+            .bind(status_code)
+            .execute(db)
+            .await?;
+    
+            return Ok(());
+        }
+        Settlement::Retry(delay) => {
+            info!(
+                "Webhook delivery {} to {} failed (attempt {}): {}",
+                delivery.delivery_id, delivery.url, attempts, error.as_deref().unwrap_or_default()
+            );
+            ("pending", delay)
+        }
+        Settlement::Dead => {
+            warn!(
+                "Webhook delivery {} to {} dead-lettered after {} attempts: {}",
+                delivery.delivery_id, delivery.url, attempts, error.as_deref().unwrap_or_default()
+            );
+            ("dead", retry_delay(attempts))
+        }
+    };
+    
+    sqlx::query(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = $2, attempts = $3, last_status_code = $4, last_error = $5,
+            next_attempt_at = NOW() + make_interval(secs => $6)
+        WHERE id = $1
+        "#
+    )
+    .bind(delivery.delivery_id)
+    .bind(status)
+    .bind(attempts)
+    .bind(status_code)
+    .bind(error)
+    .bind(delay.as_secs_f64())
+    .execute(db)
+    .await?;
+    
+    Ok(())
+}
+
+/// Backoff after `attempts` failed attempts
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    RETRY_BASE.saturating_mul(1 << exponent).min(RETRY_MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::Mutex;
+    
+    /// Requests a stand-in endpoint received, as headers and body
+    type Received = Arc<Mutex<Vec<(HeaderMap, Vec<u8>)>>>;
+    
+    /// Serve a local endpoint answering every POST with `status`, returning
+    /// its URL and the requests it receives
+    async fn stand_in(status: StatusCode) -> (String, Received) {
+        let received = Received::default();
+        let handler = move |State(received): State<Received>, headers: HeaderMap, body: Bytes| async move {
+            received.lock().unwrap().push((headers, body.to_vec()));
+            status
+        };
+        let app = Router::new()
+            .route("/hook", post(handler))
+            .with_state(received.clone());
+    
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    
+        (url, received)
+    }
+    
+    fn due(url: String, attempts: i32) -> DueDelivery {
+        DueDelivery {
+            delivery_id: 7,
+            attempts,
+            url,
+            secret: "whsec_test".to_string(),
+            event: StreamEvent {
+                id: 42,
+                contract: "iot_pipeline".to_string(),
+                event_type: "DataSubmitted".to_string(),
+                owner: None,
+                device: None,
+                block_number: 100,
+                tx_hash: [0xab; 32].into(),
+                payload: serde_json::json!({"id": 1}),
+                created_at: chrono::Utc::now(),
+            },
+        }
+    }
+    
+    #[test]
+    fn signs_timestamp_and_body() {
+        // HMAC-SHA256 keyed "whsec_test" of `1700000000.{"id":1}`
+        assert_eq!(
+            sign("whsec_test", 1_700_000_000, br#"{"id":1}"#),
+            "sha256=2f441ba4b3b2d50d28a9ab9d9fd8880376ecd1eb5d0435401553f5d8d0a5dcf8",
+        );
+    }
+    
+    #[tokio::test]
+    async fn failed_delivery_backs_off_then_dead_letters() {
+        let (url, received) = stand_in(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let client = reqwest::Client::new();
+        let mut delivery = due(url, 0);
+    
+        loop {
+            let (status_code, error) = attempt(&client, &delivery).await;
+            assert_eq!(status_code, Some(500));
+            delivery.attempts += 1;
+    
+            match settle(delivery.attempts, error.as_deref()) {
+                Settlement::Retry(delay) => {
+                    assert!(delivery.attempts < MAX_ATTEMPTS);
+                    assert_eq!(delay, retry_delay(delivery.attempts));
+                }
+                Settlement::Dead => break,
+                Settlement::Delivered => panic!("a 500 answer was marked delivered"),
+            }
+        }
+    
+        assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+        assert_eq!(received.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    
+        assert_eq!(retry_delay(1), RETRY_BASE);
+        assert_eq!(retry_delay(2), RETRY_BASE * 2);
+        assert_eq!(retry_delay(3), RETRY_BASE * 4);
+        assert_eq!(retry_delay(MAX_ATTEMPTS * 2), RETRY_MAX);
+    }
+    
+    #[tokio::test]
+    async fn successful_delivery_is_delivered_once() {
+        let (url, received) = stand_in(StatusCode::NO_CONTENT).await;
+        let delivery = due(url, 0);
+    
+        let (status_code, error) = attempt(&reqwest::Client::new(), &delivery).await;
+        assert_eq!(status_code, Some(204));
+        assert_eq!(settle(delivery.attempts + 1, error.as_deref()), Settlement::Delivered);
+    
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+    
+        let (headers, body) = &received[0];
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+        let timestamp: i64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(header(SIGNATURE_HEADER), sign(&delivery.secret, timestamp, body));
+        assert_eq!(header(EVENT_HEADER), "DataSubmitted");
+        assert_eq!(header(DELIVERY_HEADER), "7");
+        assert_eq!(serde_json::from_slice::<StreamEvent>(body).unwrap().id, 42);
+    }
+    
+    #[tokio::test]
+    async fn internal_targets_are_refused() {
+        let refused = [
+            "http://127.0.0.1/hook",
+            "http://10.0.0.5/",
+            "http://[::1]/",
+            "http://[::ffff:192.168.1.1]/",
+            "http://169.254.169.254/latest/meta-data",
+            "ftp://93.184.216.34/",
+        ];
+        for url in refused {
+            assert!(check_url(&Url::parse(url).unwrap()).await.is_err(), "{} was accepted", url);
+        }
+        assert!(check_url(&Url::parse("http://93.184.216.34/hook").unwrap()).await.is_ok());
+    }
+}